@@ -0,0 +1,167 @@
+//! Translation of ANSI escape sequences embedded in log lines into styles.
+//!
+//! Only SGR sequences (`ESC [ ... m`) affect styling; other CSI and OSC
+//! sequences are stripped so they don't show up as garbage.
+
+use ratatui::style::{Color, Modifier, Style};
+use std::borrow::Cow;
+
+const ESC: char = '\x1b';
+
+/// A styled byte range of the printable text returned by [`parse`].
+pub type Run = (usize, usize, Style);
+
+/// Splits `line` into its printable text and the styles its SGR sequences
+/// apply to ranges of that text. Lines without escapes are borrowed as-is.
+pub fn parse(line: &str) -> (Cow<'_, str>, Vec<Run>) {
+    if !line.contains(ESC) {
+        return (Cow::Borrowed(line), Vec::new());
+    }
+
+    let mut text = String::with_capacity(line.len());
+    let mut runs = Vec::new();
+    let mut state = SgrState::default();
+    let mut run_start = 0;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != ESC {
+            text.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            // CSI: parameters, intermediates, then a final byte.
+            Some('[') => {
+                let mut params = String::new();
+                let mut terminator = None;
+                for c in chars.by_ref() {
+                    if ('\x40'..='\x7e').contains(&c) {
+                        terminator = Some(c);
+                        break;
+                    }
+                    params.push(c);
+                }
+                if terminator == Some('m') {
+                    let before = state.style();
+                    state.apply(&params);
+                    if state.style() != before {
+                        if text.len() > run_start && before != Style::default() {
+                            runs.push((run_start, text.len(), before));
+                        }
+                        run_start = text.len();
+                    }
+                }
+            }
+            // OSC: terminated by BEL or ESC backslash.
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' || (c == ESC && chars.next_if_eq(&'\\').is_some()) {
+                        break;
+                    }
+                }
+            }
+            // Other two-character escapes, e.g. charset selection.
+            Some(_) | None => {}
+        }
+    }
+
+    let style = state.style();
+    if text.len() > run_start && style != Style::default() {
+        runs.push((run_start, text.len(), style));
+    }
+    (Cow::Owned(text), runs)
+}
+
+#[derive(Default)]
+struct SgrState {
+    fg: Option<Color>,
+    bg: Option<Color>,
+    modifiers: Modifier,
+}
+
+impl SgrState {
+    fn style(&self) -> Style {
+        Style {
+            fg: self.fg,
+            bg: self.bg,
+            add_modifier: self.modifiers,
+            ..Style::default()
+        }
+    }
+
+    fn apply(&mut self, params: &str) {
+        let codes: Vec<u16> = params
+            .split([';', ':'])
+            .map(|code| code.parse().unwrap_or(0))
+            .collect();
+
+        let mut codes = codes.into_iter();
+        while let Some(code) = codes.next() {
+            match code {
+                0 => *self = SgrState::default(),
+                1 => self.modifiers.insert(Modifier::BOLD),
+                2 => self.modifiers.insert(Modifier::DIM),
+                3 => self.modifiers.insert(Modifier::ITALIC),
+                4 => self.modifiers.insert(Modifier::UNDERLINED),
+                5 => self.modifiers.insert(Modifier::SLOW_BLINK),
+                7 => self.modifiers.insert(Modifier::REVERSED),
+                9 => self.modifiers.insert(Modifier::CROSSED_OUT),
+                22 => self.modifiers.remove(Modifier::BOLD | Modifier::DIM),
+                23 => self.modifiers.remove(Modifier::ITALIC),
+                24 => self.modifiers.remove(Modifier::UNDERLINED),
+                25 => self.modifiers.remove(Modifier::SLOW_BLINK),
+                27 => self.modifiers.remove(Modifier::REVERSED),
+                29 => self.modifiers.remove(Modifier::CROSSED_OUT),
+                30..=37 => self.fg = Some(basic_color(code - 30)),
+                38 => self.fg = extended_color(&mut codes),
+                39 => self.fg = None,
+                40..=47 => self.bg = Some(basic_color(code - 40)),
+                48 => self.bg = extended_color(&mut codes),
+                49 => self.bg = None,
+                90..=97 => self.fg = Some(bright_color(code - 90)),
+                100..=107 => self.bg = Some(bright_color(code - 100)),
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Parses the `5;n` (256-color) or `2;r;g;b` (truecolor) tail of a 38/48
+/// code.
+fn extended_color(codes: &mut impl Iterator<Item = u16>) -> Option<Color> {
+    match codes.next()? {
+        5 => Some(Color::Indexed(codes.next()? as u8)),
+        2 => {
+            let (r, g, b) = (codes.next()?, codes.next()?, codes.next()?);
+            Some(Color::Rgb(r as u8, g as u8, b as u8))
+        }
+        _ => None,
+    }
+}
+
+fn basic_color(index: u16) -> Color {
+    match index {
+        0 => Color::Black,
+        1 => Color::Red,
+        2 => Color::Green,
+        3 => Color::Yellow,
+        4 => Color::Blue,
+        5 => Color::Magenta,
+        6 => Color::Cyan,
+        _ => Color::Gray,
+    }
+}
+
+fn bright_color(index: u16) -> Color {
+    match index {
+        0 => Color::DarkGray,
+        1 => Color::LightRed,
+        2 => Color::LightGreen,
+        3 => Color::LightYellow,
+        4 => Color::LightBlue,
+        5 => Color::LightMagenta,
+        6 => Color::LightCyan,
+        _ => Color::White,
+    }
+}
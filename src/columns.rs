@@ -0,0 +1,83 @@
+//! The column view of structured lines chosen with `:columns`.
+
+use crate::{json, logfmt, syslog};
+use std::cell::RefCell;
+
+/// Columns stop widening past this, so one long value in the middle
+/// doesn't push the rest off screen.
+const MAX_COLUMN_WIDTH: usize = 40;
+
+pub struct Columns {
+    keys: Vec<String>,
+    /// Widest value seen so far in each column. Growing them as lines are
+    /// shown keeps the columns aligned while scrolling.
+    widths: RefCell<Vec<usize>>,
+}
+
+impl Columns {
+    /// Parses a comma-separated list of keys, e.g. `level,msg,ts`.
+    pub fn new(spec: &str) -> Option<Columns> {
+        let keys: Vec<String> = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|key| !key.is_empty())
+            .map(str::to_string)
+            .collect();
+        if keys.is_empty() {
+            return None;
+        }
+        let widths = RefCell::new(keys.iter().map(|key| key.chars().count()).collect());
+        Some(Columns { keys, widths })
+    }
+
+    pub fn keys(&self) -> &[String] {
+        &self.keys
+    }
+
+    pub fn widths(&self) -> Vec<usize> {
+        self.widths.borrow().clone()
+    }
+
+    /// Formats the chosen keys of a JSON object, logfmt or, if `syslog`,
+    /// syslog `line` as aligned columns. Returns `None` for other lines.
+    pub fn format(&self, line: &str, syslog: bool) -> Option<String> {
+        let fields = fields(line, syslog)?;
+        let mut widths = self.widths.borrow_mut();
+        let mut formatted = String::new();
+        for (i, key) in self.keys.iter().enumerate() {
+            let value = fields
+                .iter()
+                .find(|(name, _)| name == key)
+                .map_or("", |(_, value)| value.as_str());
+            // Keep values on one line.
+            let value = value.replace(['\n', '\r', '\t'], " ");
+            if i + 1 == self.keys.len() {
+                formatted.push_str(&value);
+            } else {
+                let len = value.chars().count();
+                widths[i] = widths[i].max(len.min(MAX_COLUMN_WIDTH));
+                formatted.push_str(&format!("{value:<width$}  ", width = widths[i]));
+            }
+        }
+        Some(formatted.trim_end().to_string())
+    }
+}
+
+/// Key/value pairs of a JSON object, logfmt or syslog line.
+pub fn fields(line: &str, syslog: bool) -> Option<Vec<(String, String)>> {
+    // A syslog message can itself look like logfmt.
+    if syslog && let Some(message) = syslog::Message::parse(line) {
+        return Some(message.fields());
+    }
+    if line.trim_start().starts_with('{')
+        && let Some(json::Value::Object(members)) = json::parse(line)
+    {
+        return Some(
+            members
+                .into_iter()
+                .map(|(name, value)| (name, value.text().into_owned()))
+                .collect(),
+        );
+    }
+    logfmt::parse(line)
+}
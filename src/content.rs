@@ -0,0 +1,670 @@
+//! The lines of the log, either held in memory or read from the file on
+//! demand through an index of where each line starts.
+//!
+//! Indexing keeps memory use to a few bytes per line, so multi-gigabyte
+//! files open without being read into memory. The indexed lines are read
+//! as needed, or borrowed from a memory map with `--mmap`. Files are
+//! loaded on a background thread by a [`Loader`] so the UI stays
+//! responsive meanwhile.
+
+use crate::{encoding::Encoding, gzip, mmap::Mmap};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::HashMap,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, TryRecvError},
+    thread,
+};
+
+/// Bytes read at once when a line isn't in the cached block, so reading
+/// neighbouring lines (a screenful, or a scan for a search) doesn't cost a
+/// read per line.
+const BLOCK_SIZE: usize = 64 * 1024;
+
+/// Bytes sampled from the start of a file to tell whether it's binary.
+const BINARY_SAMPLE_SIZE: usize = 8 * 1024;
+
+/// Bytes scanned between progress reports while indexing.
+const INDEX_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// How files are loaded, from the command line.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Options {
+    /// Memory map files instead of reading lines on demand.
+    pub mmap: bool,
+    /// Encoding of the files, overriding detection from a byte order mark.
+    pub encoding: Option<Encoding>,
+    /// Load only this many lines from the end of files.
+    pub tail: Option<usize>,
+}
+
+pub struct Content {
+    source: Source,
+    /// Whether the data ended in a newline; if not, appended data
+    /// continues the last line.
+    last_line_complete: bool,
+    /// Byte ending lines: a newline, or a carriage return in files from
+    /// classic Mac OS, which have no newlines. Carriage returns before
+    /// newlines are left out of lines too.
+    separator: u8,
+    /// Lines found to contain invalid UTF-8 when loaded, which are shown
+    /// with replacement characters.
+    invalid_lines: usize,
+    /// Text replacing lines, by index, as returned by the Lua `on_line`
+    /// hook.
+    replaced: HashMap<usize, String>,
+}
+
+enum Source {
+    /// Piped input, decompressed files and the welcome text.
+    Memory(Vec<String>),
+    Indexed(Index),
+}
+
+struct Index {
+    file: File,
+    /// Byte offset of the start of each line.
+    starts: Vec<u64>,
+    /// Bytes of the file indexed so far, where the last line ends.
+    end: u64,
+    data: Data,
+}
+
+/// Where the bytes of indexed lines come from.
+enum Data {
+    /// Read from `file` on demand. Holds the most recently read block of
+    /// the file and its offset.
+    Read(RefCell<(u64, Vec<u8>)>),
+    /// Borrowed from a mapping of `file`.
+    Mapped(Mmap),
+}
+
+impl Content {
+    pub fn from_lines(lines: Vec<String>) -> Content {
+        Content {
+            source: Source::Memory(lines),
+            last_line_complete: true,
+            separator: b'\n',
+            invalid_lines: 0,
+            replaced: HashMap::new(),
+        }
+    }
+
+    /// Content indexing `file`, with no lines until they're added with
+    /// [`Loader::take_indexed`] while a [`Loader`] indexes the same file.
+    pub fn indexed(file: File) -> Content {
+        Content {
+            source: Source::Indexed(Index {
+                file,
+                starts: Vec::new(),
+                end: 0,
+                data: Data::Read(RefCell::new((0, Vec::new()))),
+            }),
+            last_line_complete: true,
+            separator: b'\n',
+            invalid_lines: 0,
+            replaced: HashMap::new(),
+        }
+    }
+
+    /// Decodes `bytes` in `encoding`, or the one indicated by their byte
+    /// order mark, defaulting to UTF-8.
+    pub fn decode(bytes: &[u8], encoding: Option<Encoding>) -> Content {
+        let bom = Encoding::from_bom(bytes);
+        let encoding = encoding.or(bom.map(|(detected, _)| detected));
+        let bytes = match bom {
+            Some((detected, len)) if Some(detected) == encoding => &bytes[len..],
+            _ => bytes,
+        };
+        let text = encoding.unwrap_or(Encoding::Utf8).decode(bytes);
+        let separator = detect_separator(text.as_bytes());
+        let invalid_lines = match encoding {
+            None | Some(Encoding::Utf8) => count_invalid_lines(bytes, separator),
+            Some(_) => 0,
+        };
+        Content {
+            source: Source::Memory(split_lines(&text, separator).map(str::to_string).collect()),
+            last_line_complete: text.is_empty() || text.ends_with(char::from(separator)),
+            separator,
+            invalid_lines,
+            replaced: HashMap::new(),
+        }
+    }
+
+    /// Drops all but the last `lines` lines, for `--tail` on content that
+    /// can't be indexed from part way through.
+    pub fn keep_last(&mut self, lines: usize) {
+        let Source::Memory(all) = &mut self.source else {
+            return;
+        };
+        all.drain(..all.len().saturating_sub(lines));
+    }
+
+    pub fn invalid_lines(&self) -> usize {
+        self.invalid_lines
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.source {
+            Source::Memory(lines) => lines.len(),
+            Source::Indexed(index) => index.starts.len(),
+        }
+    }
+
+    /// Bytes of the file indexed so far, or `None` if the content isn't
+    /// indexed and can't be extended from the file.
+    pub fn byte_len(&self) -> Option<u64> {
+        match &self.source {
+            Source::Memory(_) => None,
+            Source::Indexed(index) => Some(index.end),
+        }
+    }
+
+    /// Returns line `index` without its line terminator, or the text
+    /// replacing it. Invalid UTF-8 is replaced, and a line that can no
+    /// longer be read (e.g. the file was truncated underneath us) comes back
+    /// empty.
+    pub fn line(&self, index: usize) -> Cow<'_, str> {
+        match self.replaced.get(&index) {
+            Some(text) => Cow::Borrowed(text),
+            None => self.original_line(index),
+        }
+    }
+
+    /// Returns line `index` as read, ignoring any text replacing it.
+    pub fn original_line(&self, index: usize) -> Cow<'_, str> {
+        match &self.source {
+            Source::Memory(lines) => {
+                let line = &lines[index];
+                Cow::Borrowed(line.strip_suffix('\r').unwrap_or(line))
+            }
+            Source::Indexed(lines) => {
+                let start = lines.starts[index];
+                let end = lines.starts.get(index + 1).copied().unwrap_or(lines.end);
+                match &lines.data {
+                    Data::Read(block) => {
+                        let bytes = read(&lines.file, block, start, end).unwrap_or_default();
+                        let (line, _) = trim_newline(&bytes, self.separator);
+                        Cow::Owned(String::from_utf8_lossy(line).into_owned())
+                    }
+                    Data::Mapped(map) => {
+                        let bytes = map.get(start as usize..end as usize).unwrap_or_default();
+                        String::from_utf8_lossy(trim_newline(bytes, self.separator).0)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Whether line `index` ended with a carriage return before its
+    /// newline, as in files from Windows.
+    pub fn has_carriage_return(&self, index: usize) -> bool {
+        match &self.source {
+            Source::Memory(lines) => lines[index].ends_with('\r'),
+            Source::Indexed(lines) => {
+                let start = lines.starts[index];
+                let end = lines.starts.get(index + 1).copied().unwrap_or(lines.end);
+                match &lines.data {
+                    Data::Read(block) => read(&lines.file, block, start, end)
+                        .is_ok_and(|bytes| trim_newline(&bytes, self.separator).1),
+                    Data::Mapped(map) => map
+                        .get(start as usize..end as usize)
+                        .is_some_and(|bytes| trim_newline(bytes, self.separator).1),
+                }
+            }
+        }
+    }
+
+    /// Shows `text` instead of line `index`, or the line as read again if
+    /// `None`.
+    pub fn replace_line(&mut self, index: usize, text: Option<String>) {
+        match text {
+            Some(text) => self.replaced.insert(index, text),
+            None => self.replaced.remove(&index),
+        };
+    }
+
+    /// Takes over the text replacing lines of `other`, which has the same
+    /// lines.
+    pub fn take_replaced(&mut self, other: &mut Content) {
+        self.replaced = std::mem::take(&mut other.replaced);
+    }
+
+    /// Shows every line as read again.
+    pub fn clear_replaced(&mut self) {
+        self.replaced.clear();
+    }
+
+    /// Removes all lines, e.g. when the file was truncated.
+    pub fn clear(&mut self) {
+        self.replaced.clear();
+        match &mut self.source {
+            Source::Memory(lines) => lines.clear(),
+            Source::Indexed(index) => {
+                index.starts.clear();
+                index.end = 0;
+            }
+        }
+        self.unmap();
+        self.last_line_complete = true;
+    }
+
+    /// Reads lines from the file from now on rather than through its
+    /// mapping, once the file shrank: the mapping can't be read past the
+    /// new end of the file.
+    pub fn unmap(&mut self) {
+        if let Source::Indexed(index) = &mut self.source
+            && let Data::Mapped(_) = index.data
+        {
+            index.data = Data::Read(RefCell::new((0, Vec::new())));
+        }
+    }
+
+    /// Adds `bytes` appended to the file, continuing the last line if it
+    /// wasn't complete. Returns the index of the first new or changed line.
+    pub fn append(&mut self, bytes: &[u8]) -> usize {
+        let len = self.len();
+        let continues_last = !self.last_line_complete && len > 0 && !bytes.is_empty();
+        match &mut self.source {
+            Source::Memory(lines) => {
+                let text = String::from_utf8_lossy(bytes);
+                let mut new_lines = split_lines(&text, self.separator);
+                if continues_last
+                    && let (Some(last), Some(rest)) = (lines.last_mut(), new_lines.next())
+                {
+                    last.push_str(rest);
+                }
+                lines.extend(new_lines.map(str::to_string));
+                if !text.is_empty() {
+                    self.last_line_complete = text.ends_with(char::from(self.separator));
+                }
+            }
+            Source::Indexed(index) => {
+                index_lines(
+                    &mut index.starts,
+                    &mut self.last_line_complete,
+                    index.end,
+                    bytes,
+                    self.separator,
+                );
+                index.end += bytes.len() as u64;
+                // Lines past the end of the mapping read as empty until it
+                // is remapped.
+                if let Data::Mapped(map) = &mut index.data
+                    && index.end > map.len() as u64
+                    // SAFETY: `--mmap` trusts the file to only grow; once it
+                    // shrank, `unmap` drops the mapping.
+                    && let Ok(remapped) = unsafe { Mmap::map(&index.file) }
+                {
+                    *map = remapped;
+                }
+            }
+        }
+        if continues_last { len - 1 } else { len }
+    }
+}
+
+/// Whether the file at `path` looks binary rather than text: its start has
+/// a NUL byte, or many control characters besides whitespace and escapes.
+/// UTF-16 text, told by `encoding` or its byte order mark, and compressed
+/// files are never binary.
+pub fn looks_binary(path: &Path, encoding: Option<Encoding>) -> io::Result<bool> {
+    if gzip::is_gzip(path)? {
+        return Ok(false);
+    }
+    let mut sample = Vec::new();
+    File::open(path)?
+        .take(BINARY_SAMPLE_SIZE as u64)
+        .read_to_end(&mut sample)?;
+    let encoding = encoding.or(Encoding::from_bom(&sample).map(|(detected, _)| detected));
+    if matches!(encoding, Some(Encoding::Utf16Le | Encoding::Utf16Be)) {
+        return Ok(false);
+    }
+    let control = sample
+        .iter()
+        .filter(|&&byte| (byte < 0x20 && !b"\t\n\r\x0c\x1b".contains(&byte)) || byte == 0x7f)
+        .count();
+    Ok(sample.contains(&0) || control * 10 > sample.len())
+}
+
+/// The error ending a load once nothing waits for it any more.
+fn abandoned() -> io::Error {
+    io::Error::new(io::ErrorKind::Interrupted, "load abandoned")
+}
+
+/// Reads the bytes `start..end` of `file`, through the cached `block`.
+fn read(file: &File, block: &RefCell<(u64, Vec<u8>)>, start: u64, end: u64) -> io::Result<Vec<u8>> {
+    let len = (end - start) as usize;
+    let mut block = block.borrow_mut();
+    let (block_start, data) = &mut *block;
+    if start < *block_start || end > *block_start + data.len() as u64 {
+        let mut file = file;
+        file.seek(SeekFrom::Start(start))?;
+        data.clear();
+        file.take(len.max(BLOCK_SIZE) as u64).read_to_end(data)?;
+        *block_start = start;
+        if data.len() < len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+    }
+    let offset = (start - *block_start) as usize;
+    Ok(data[offset..offset + len].to_vec())
+}
+
+/// Counts the lines in `bytes` that aren't valid UTF-8.
+fn count_invalid_lines(bytes: &[u8], separator: u8) -> usize {
+    if std::str::from_utf8(bytes).is_ok() {
+        return 0;
+    }
+    bytes
+        .split(|&byte| byte == separator)
+        .filter(|line| std::str::from_utf8(line).is_err())
+        .count()
+}
+
+/// The byte ending the lines of a file starting with `head`: a carriage
+/// return if there are only those, else a newline.
+fn detect_separator(head: &[u8]) -> u8 {
+    if !head.contains(&b'\n') && head.contains(&b'\r') {
+        b'\r'
+    } else {
+        b'\n'
+    }
+}
+
+/// The lines of `text`, each with any carriage return before its newline.
+fn split_lines(text: &str, separator: u8) -> std::str::SplitTerminator<'_, char> {
+    text.split_terminator(char::from(separator))
+}
+
+/// Returns `line` without its terminator, and whether that included a
+/// carriage return before the newline.
+fn trim_newline(line: &[u8], separator: u8) -> (&[u8], bool) {
+    let line = line.strip_suffix(&[separator]).unwrap_or(line);
+    match line.strip_suffix(b"\r") {
+        Some(trimmed) if separator == b'\n' => (trimmed, true),
+        _ => (line, false),
+    }
+}
+
+/// The offset in `file` where its last `lines` lines, ended by
+/// `separator`, start. The file is read backwards from its end.
+fn tail_start(file: &mut File, lines: usize, separator: u8) -> io::Result<u64> {
+    let len = file.metadata()?.len();
+    if lines == 0 {
+        return Ok(len);
+    }
+    let mut block = vec![0; INDEX_CHUNK_SIZE];
+    let mut found = 0;
+    let mut position = len;
+    while position > 0 {
+        let size = position.min(INDEX_CHUNK_SIZE as u64) as usize;
+        position -= size as u64;
+        file.seek(SeekFrom::Start(position))?;
+        file.read_exact(&mut block[..size])?;
+        for (i, &byte) in block[..size].iter().enumerate().rev() {
+            let offset = position + i as u64;
+            // The separator ending the last line doesn't start another.
+            if byte == separator && offset + 1 < len {
+                found += 1;
+                if found == lines {
+                    return Ok(offset + 1);
+                }
+            }
+        }
+    }
+    Ok(0)
+}
+
+/// Records in `starts` the lines beginning in `bytes`, which were read at
+/// file `offset`. `complete` tracks whether the data so far ended with a
+/// `separator`, i.e. whether the next byte starts a new line.
+fn index_lines(
+    starts: &mut Vec<u64>,
+    complete: &mut bool,
+    offset: u64,
+    bytes: &[u8],
+    separator: u8,
+) {
+    let mut line_start = *complete;
+    for (i, &byte) in bytes.iter().enumerate() {
+        if line_start {
+            starts.push(offset + i as u64);
+        }
+        line_start = byte == separator;
+    }
+    if !bytes.is_empty() {
+        *complete = line_start;
+    }
+}
+
+/// Loads a file into [`Content`] on a background thread.
+pub struct Loader {
+    pub path: PathBuf,
+    /// Size of the file when loading started.
+    total: u64,
+    /// Bytes processed so far.
+    loaded: u64,
+    /// Starts of the lines indexed since [`Loader::take_indexed`] was last
+    /// called.
+    starts: Vec<u64>,
+    /// Whether the bytes indexed so far end with a newline.
+    complete: bool,
+    separator: u8,
+    progress: Receiver<Progress>,
+}
+
+enum Progress {
+    /// More of the file was indexed: the starts of the lines found, where
+    /// the indexed bytes end, and the byte ending lines.
+    Indexed {
+        starts: Vec<u64>,
+        end: u64,
+        complete: bool,
+        separator: u8,
+    },
+    Done(io::Result<Content>),
+}
+
+impl Loader {
+    pub fn spawn(path: PathBuf, options: Options) -> Loader {
+        let total = std::fs::metadata(&path).map_or(0, |metadata| metadata.len());
+        let (sender, progress) = mpsc::channel();
+        let thread_path = path.clone();
+        thread::spawn(move || {
+            let result = load(&thread_path, options, |starts, end, complete, separator| {
+                // The receiver is only gone if the load was abandoned.
+                sender
+                    .send(Progress::Indexed {
+                        starts: starts.to_vec(),
+                        end,
+                        complete,
+                        separator,
+                    })
+                    .is_ok()
+            });
+            let _ = sender.send(Progress::Done(result));
+        });
+        Loader {
+            path,
+            total,
+            loaded: 0,
+            starts: Vec::new(),
+            complete: true,
+            separator: b'\n',
+            progress,
+        }
+    }
+
+    /// Returns the loaded content, or the error loading it, once done.
+    pub fn poll(&mut self) -> Option<io::Result<Content>> {
+        loop {
+            match self.progress.try_recv() {
+                Ok(Progress::Indexed {
+                    starts,
+                    end,
+                    complete,
+                    separator,
+                }) => {
+                    self.starts.extend(starts);
+                    self.loaded = end;
+                    self.complete = complete;
+                    self.separator = separator;
+                }
+                Ok(Progress::Done(result)) => return Some(result),
+                Err(TryRecvError::Empty) => return None,
+                Err(TryRecvError::Disconnected) => {
+                    return Some(Err(io::Error::other("loading thread exited")));
+                }
+            }
+        }
+    }
+
+    pub fn percent(&self) -> u64 {
+        (self.loaded * 100).checked_div(self.total).unwrap_or(100)
+    }
+
+    /// Adds the lines indexed since the last call to `content`, made with
+    /// [`Content::indexed`], so they can be shown before the load is done.
+    /// Returns the index of the first new or changed line, if any.
+    ///
+    /// Only plain UTF-8 files are indexed as they load; others arrive
+    /// whole when done.
+    pub fn take_indexed(&mut self, content: &mut Content) -> Option<usize> {
+        let Source::Indexed(index) = &mut content.source else {
+            return None;
+        };
+        if self.loaded == index.end {
+            return None;
+        }
+        let len = index.starts.len();
+        let continues_last = !content.last_line_complete && len > 0;
+        index.starts.append(&mut self.starts);
+        index.end = self.loaded;
+        content.last_line_complete = self.complete;
+        content.separator = self.separator;
+        Some(if continues_last { len - 1 } else { len })
+    }
+}
+
+/// Loads the file at `path`. As plain UTF-8 files are indexed, `progress`
+/// is given the starts of the new lines, where the indexed bytes end,
+/// whether they end with a line separator, and which byte that is. It
+/// returns whether the load is still wanted, and reading stops if not.
+fn load(
+    path: &Path,
+    options: Options,
+    mut progress: impl FnMut(&[u64], u64, bool, u8) -> bool,
+) -> io::Result<Content> {
+    let decode = |bytes: &[u8]| {
+        let mut content = Content::decode(bytes, options.encoding);
+        if let Some(lines) = options.tail {
+            content.keep_last(lines);
+        }
+        content
+    };
+    if gzip::is_gzip(path)? {
+        return Ok(decode(&gzip::read(path)?));
+    }
+
+    let mut file = File::open(path)?;
+    let mut head = Vec::new();
+    file.by_ref().take(3).read_to_end(&mut head)?;
+    file.seek(SeekFrom::Start(0))?;
+    let bom = Encoding::from_bom(&head);
+    match options.encoding.or(bom.map(|(detected, _)| detected)) {
+        None | Some(Encoding::Utf8) => {}
+        // Only UTF-8 can be indexed by newline bytes and read line by line.
+        Some(_) => {
+            let mut bytes = Vec::new();
+            file.read_to_end(&mut bytes)?;
+            return Ok(decode(&bytes));
+        }
+    }
+
+    let bom_len = match bom {
+        Some((Encoding::Utf8, len)) => len as u64,
+        _ => 0,
+    };
+    let mut sample = Vec::new();
+    file.by_ref()
+        .take(INDEX_CHUNK_SIZE as u64)
+        .read_to_end(&mut sample)?;
+    let separator = detect_separator(&sample);
+    // With `--tail`, indexing starts at the first of the last lines.
+    let start = match options.tail {
+        Some(lines) => tail_start(&mut file, lines, separator)?,
+        None => 0,
+    };
+    file.seek(SeekFrom::Start(start))?;
+
+    let mut starts = Vec::new();
+    let mut complete = true;
+    let mut end = start;
+    let mut invalid_lines = 0;
+    let mut reported = 0;
+    let mut report = |starts: &mut Vec<u64>, end: u64, complete: bool, separator: u8| {
+        // The first line starts after the byte order mark.
+        if reported == 0
+            && let Some(first) = starts.first_mut()
+        {
+            *first = (*first).max(bom_len);
+        }
+        let wanted = progress(&starts[reported..], end, complete, separator);
+        reported = starts.len();
+        wanted
+    };
+    let data = if options.mmap {
+        // SAFETY: as in `Content::append`.
+        let map = unsafe { Mmap::map(&file)? };
+        let tail = map.get(start as usize..).unwrap_or_default();
+        for chunk in tail.chunks(INDEX_CHUNK_SIZE) {
+            index_lines(&mut starts, &mut complete, end, chunk, separator);
+            end += chunk.len() as u64;
+            if !report(&mut starts, end, complete, separator) {
+                return Err(abandoned());
+            }
+        }
+        invalid_lines = count_invalid_lines(tail, separator);
+        Data::Mapped(map)
+    } else {
+        let mut chunk = vec![0; INDEX_CHUNK_SIZE];
+        // Lines are validated once complete, so the tail of each chunk is
+        // carried over to the next.
+        let mut partial = Vec::new();
+        loop {
+            let read = file.read(&mut chunk)?;
+            if read == 0 {
+                break;
+            }
+            index_lines(&mut starts, &mut complete, end, &chunk[..read], separator);
+            end += read as u64;
+            if !report(&mut starts, end, complete, separator) {
+                return Err(abandoned());
+            }
+
+            partial.extend_from_slice(&chunk[..read]);
+            if let Some(newline) = partial.iter().rposition(|&byte| byte == separator) {
+                invalid_lines += count_invalid_lines(&partial[..newline], separator);
+                partial.drain(..=newline);
+            }
+        }
+        invalid_lines += count_invalid_lines(&partial, separator);
+        Data::Read(RefCell::new((0, Vec::new())))
+    };
+    Ok(Content {
+        source: Source::Indexed(Index {
+            file,
+            starts,
+            end,
+            data,
+        }),
+        last_line_complete: complete,
+        separator,
+        invalid_lines,
+        replaced: HashMap::new(),
+    })
+}
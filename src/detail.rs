@@ -0,0 +1,150 @@
+//! The popup showing the whole current line, opened with Enter, with the
+//! fields of structured lines below it.
+
+use crate::{caret_notation, columns, json, next_tab_stop};
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+
+/// Field names are padded to line up their values up to this width.
+const MAX_KEY_WIDTH: usize = 20;
+
+pub struct Detail {
+    /// 1-based number of the line.
+    number: usize,
+    text: String,
+    /// Pretty-printed JSON, or the key/value pairs of a logfmt or syslog
+    /// line, if the line is structured.
+    fields: Option<Fields>,
+    /// Rows scrolled down, clamped to the popup's content when drawn.
+    pub scroll: usize,
+}
+
+enum Fields {
+    Json(String),
+    Pairs(Vec<(String, String)>),
+}
+
+impl Detail {
+    /// The detail of line `number`, whose `text` has had its escape
+    /// sequences removed.
+    pub fn new(number: usize, text: &str, syslog: bool, tab_width: usize) -> Detail {
+        let trimmed = text.trim_start();
+        let fields = match json::parse(text) {
+            Some(value) if trimmed.starts_with(['{', '[']) => Some(Fields::Json(value.pretty())),
+            _ => columns::fields(text, syslog).map(Fields::Pairs),
+        };
+        Detail {
+            number,
+            text: printable(text, tab_width),
+            fields,
+            scroll: 0,
+        }
+    }
+
+    /// Draws the popup over most of `area`, wrapping long lines.
+    pub fn render(&mut self, f: &mut Frame, area: Rect) {
+        let heading = Style::default().add_modifier(Modifier::BOLD);
+        let key_style = Style::default().fg(Color::Yellow);
+        let width = area.width.saturating_sub(4).max(area.width.min(20));
+        let inner = width.saturating_sub(2).max(1) as usize;
+
+        let mut lines: Vec<Line> = wrap(&self.text, inner).into_iter().map(Line::raw).collect();
+        match &self.fields {
+            Some(Fields::Json(pretty)) => {
+                lines.push(Line::default());
+                lines.push(Line::styled("JSON", heading));
+                for line in pretty.lines() {
+                    lines.extend(wrap(&printable(line, 2), inner).into_iter().map(Line::raw));
+                }
+            }
+            Some(Fields::Pairs(pairs)) => {
+                lines.push(Line::default());
+                lines.push(Line::styled("Fields", heading));
+                let key_width = pairs
+                    .iter()
+                    .map(|(key, _)| key.chars().count())
+                    .max()
+                    .unwrap_or(0)
+                    .min(MAX_KEY_WIDTH);
+                for (key, value) in pairs {
+                    let row = format!("{key:<key_width$}  {}", printable(value, 2));
+                    let mut rows = wrap(&row, inner).into_iter();
+                    // Keys in the color the help shows keys in.
+                    let first = rows.next().unwrap_or_default();
+                    let key_end = first
+                        .char_indices()
+                        .nth(key.chars().count())
+                        .map_or(first.len(), |(end, _)| end);
+                    lines.push(Line::from(vec![
+                        Span::styled(first[..key_end].to_string(), key_style),
+                        Span::raw(first[key_end..].to_string()),
+                    ]));
+                    lines.extend(rows.map(Line::raw));
+                }
+            }
+            None => {}
+        }
+
+        let height = (lines.len() as u16 + 2).min(area.height);
+        self.scroll = self.scroll.min(
+            lines
+                .len()
+                .saturating_sub(height.saturating_sub(2) as usize),
+        );
+        let popup = Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + (area.height - height) / 2,
+            width,
+            height,
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(format!("Line {}", self.number))
+            .title_bottom(Line::from(" j/k to scroll, any other key to close ").right_aligned());
+        f.render_widget(Clear, popup);
+        f.render_widget(
+            Paragraph::new(lines)
+                .block(block)
+                .scroll((self.scroll as u16, 0)),
+            popup,
+        );
+    }
+}
+
+/// `text` with tabs expanded and other control characters in caret
+/// notation, so each character takes a column.
+fn printable(text: &str, tab_width: usize) -> String {
+    let mut printable = String::with_capacity(text.len());
+    let mut column = 0;
+    for c in text.chars() {
+        if c == '\t' {
+            let stop = next_tab_stop(column, tab_width);
+            printable.extend(std::iter::repeat_n(' ', stop - column));
+            column = stop;
+        } else if let Some(caret) = caret_notation(c) {
+            printable.push_str(&caret);
+            column += 2;
+        } else {
+            printable.push(c);
+            column += 1;
+        }
+    }
+    printable
+}
+
+/// Splits `text` into rows of `width` characters.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let chars: Vec<char> = text.chars().collect();
+    if chars.is_empty() {
+        return vec![String::new()];
+    }
+    chars
+        .chunks(width)
+        .map(|row| row.iter().collect())
+        .collect()
+}
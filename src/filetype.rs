@@ -0,0 +1,67 @@
+//! Profiles from the config's `filetypes` section, applied to the files
+//! they match by extension or, failing that, by their first line.
+
+use crate::regex::RegexBuilder;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, path::Path};
+
+/// A profile, e.g. for JSON logs:
+///
+/// ```yaml
+/// filetypes:
+///   json:
+///     extensions: [json, jsonl]
+///     first_line: '^\{'
+///     set: json nowrap
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FileType {
+    /// Extensions of the files it applies to, without the dot.
+    #[serde(default)]
+    pub extensions: Vec<String>,
+    /// Pattern the first line is matched against, for files no profile's
+    /// extensions match.
+    #[serde(default)]
+    pub first_line: Option<String>,
+    /// Options to set, as given to `:set`.
+    #[serde(default)]
+    pub set: String,
+    /// Format of the lines: syslog or plain.
+    #[serde(default)]
+    pub format: Option<String>,
+}
+
+/// The name of the profile for files with `path`'s extension, if any.
+pub fn by_extension<'a>(filetypes: &'a BTreeMap<String, FileType>, path: &Path) -> Option<&'a str> {
+    let extension = path.extension()?.to_string_lossy();
+    filetypes
+        .iter()
+        .find(|(_, filetype)| {
+            filetype.extensions.iter().any(|wanted| {
+                wanted
+                    .trim_start_matches('.')
+                    .eq_ignore_ascii_case(&extension)
+            })
+        })
+        .map(|(name, _)| name.as_str())
+}
+
+/// The name of the first profile whose `first_line` pattern matches `line`,
+/// if any, or an error for an invalid pattern.
+pub fn by_first_line<'a>(
+    filetypes: &'a BTreeMap<String, FileType>,
+    line: &str,
+) -> Result<Option<&'a str>, String> {
+    for (name, filetype) in filetypes {
+        let Some(pattern) = &filetype.first_line else {
+            continue;
+        };
+        let regex = RegexBuilder::new(pattern)
+            .build()
+            .map_err(|err| format!("filetypes: {name}: {err}"))?;
+        if regex.is_match(line) {
+            return Ok(Some(name));
+        }
+    }
+    Ok(None)
+}
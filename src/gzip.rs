@@ -0,0 +1,494 @@
+//! Decompression of gzip files (RFC 1952) and the DEFLATE streams inside
+//! them (RFC 1951), so rotated `.gz` logs open like plain ones.
+
+use std::{
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+};
+
+const MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Header flags.
+const FHCRC: u8 = 0x02;
+const FEXTRA: u8 = 0x04;
+const FNAME: u8 = 0x08;
+const FCOMMENT: u8 = 0x10;
+
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+/// Order in which code length code lengths are stored in a dynamic block.
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+/// Whether `path` should be decompressed: it's named `*.gz` or starts with
+/// the gzip magic bytes.
+pub fn is_gzip(path: &Path) -> io::Result<bool> {
+    if path.extension().is_some_and(|ext| ext == "gz") {
+        return Ok(true);
+    }
+    let mut magic = [0; 2];
+    let read = io::Read::read(&mut fs::File::open(path)?, &mut magic)?;
+    Ok(read == 2 && magic == MAGIC)
+}
+
+/// Reads the file at `path`, decompressing it if it's gzipped.
+pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+    let data = fs::read(path)?;
+    if data.starts_with(&MAGIC) || path.extension().is_some_and(|ext| ext == "gz") {
+        decompress(&data)
+    } else {
+        Ok(data)
+    }
+}
+
+/// Decompresses gzip `data`, concatenating all its members as `gunzip`
+/// does.
+pub fn decompress(data: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(data.len() * 4);
+    let mut rest = data;
+    loop {
+        rest = member(rest, &mut out)?;
+        if !rest.starts_with(&MAGIC) {
+            return Ok(out);
+        }
+    }
+}
+
+/// Decompresses the gzip member at the start of `data` into `out`,
+/// returning the data following it.
+fn member<'a>(data: &'a [u8], out: &mut Vec<u8>) -> io::Result<&'a [u8]> {
+    if data.len() < 10 || data[..2] != MAGIC {
+        return Err(invalid("not in gzip format"));
+    }
+    if data[2] != 8 {
+        return Err(invalid("unknown compression method"));
+    }
+
+    let flags = data[3];
+    let mut pos = 10;
+    if flags & FEXTRA != 0 {
+        let len = u16::from_le_bytes(take(data, pos, 2)?.try_into().unwrap()) as usize;
+        pos += 2 + len;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            let end = data
+                .get(pos..)
+                .and_then(|rest| rest.iter().position(|&byte| byte == 0))
+                .ok_or_else(truncated)?;
+            pos += end + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        pos += 2;
+    }
+
+    let start = out.len();
+    let mut reader = BitReader::new(data.get(pos..).ok_or_else(truncated)?);
+    inflate(&mut reader, out)?;
+    pos += reader.byte_pos();
+
+    let trailer = take(data, pos, 8)?;
+    let crc = u32::from_le_bytes(trailer[..4].try_into().unwrap());
+    let size = u32::from_le_bytes(trailer[4..].try_into().unwrap());
+    if crc32(&out[start..]) != crc || (out.len() - start) as u32 != size {
+        return Err(invalid("checksum mismatch"));
+    }
+    Ok(&data[pos + 8..])
+}
+
+fn take(data: &[u8], pos: usize, len: usize) -> io::Result<&[u8]> {
+    data.get(pos..pos + len).ok_or_else(truncated)
+}
+
+/// Decodes a raw DEFLATE stream, appending to `out`.
+fn inflate(reader: &mut BitReader, out: &mut Vec<u8>) -> io::Result<()> {
+    loop {
+        let last = reader.bits(1)? == 1;
+        match reader.bits(2)? {
+            0 => stored(reader, out)?,
+            1 => {
+                let (literals, distances) = fixed_codes();
+                codes(reader, out, &literals, &distances)?;
+            }
+            2 => {
+                let (literals, distances) = dynamic_codes(reader)?;
+                codes(reader, out, &literals, &distances)?;
+            }
+            _ => return Err(invalid("invalid block type")),
+        }
+        if last {
+            return Ok(());
+        }
+    }
+}
+
+fn stored(reader: &mut BitReader, out: &mut Vec<u8>) -> io::Result<()> {
+    reader.align();
+    let len = reader.bits(16)?;
+    if reader.bits(16)? != !len & 0xffff {
+        return Err(invalid("stored block length mismatch"));
+    }
+    for _ in 0..len {
+        out.push(reader.bits(8)? as u8);
+    }
+    Ok(())
+}
+
+/// Decodes the compressed data of a block using the given codes.
+fn codes(
+    reader: &mut BitReader,
+    out: &mut Vec<u8>,
+    literals: &Huffman,
+    distances: &Huffman,
+) -> io::Result<()> {
+    loop {
+        let symbol = literals.decode(reader)? as usize;
+        if symbol < 256 {
+            out.push(symbol as u8);
+            continue;
+        }
+        if symbol == 256 {
+            return Ok(());
+        }
+
+        let index = symbol - 257;
+        if index >= LENGTH_BASE.len() {
+            return Err(invalid("invalid length code"));
+        }
+        let len = LENGTH_BASE[index] as usize + reader.bits(LENGTH_EXTRA[index] as u32)? as usize;
+
+        let index = distances.decode(reader)? as usize;
+        if index >= DISTANCE_BASE.len() {
+            return Err(invalid("invalid distance code"));
+        }
+        let distance =
+            DISTANCE_BASE[index] as usize + reader.bits(DISTANCE_EXTRA[index] as u32)? as usize;
+        if distance > out.len() {
+            return Err(invalid("distance too far back"));
+        }
+
+        // Byte by byte, since the copy may overlap what it produces.
+        let from = out.len() - distance;
+        for i in 0..len {
+            out.push(out[from + i]);
+        }
+    }
+}
+
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [0; 288];
+    lengths[..144].fill(8);
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    lengths[280..].fill(8);
+    // Both are complete codes, which can't fail to build.
+    let literals = Huffman::new(&lengths).unwrap();
+    let distances = Huffman::new(&[5; 30]).unwrap();
+    (literals, distances)
+}
+
+fn dynamic_codes(reader: &mut BitReader) -> io::Result<(Huffman, Huffman)> {
+    let literal_count = reader.bits(5)? as usize + 257;
+    let distance_count = reader.bits(5)? as usize + 1;
+    let code_length_count = reader.bits(4)? as usize + 4;
+    if literal_count > 286 || distance_count > 30 {
+        return Err(invalid("too many length or distance codes"));
+    }
+
+    let mut code_lengths = [0; 19];
+    for &index in &CODE_LENGTH_ORDER[..code_length_count] {
+        code_lengths[index] = reader.bits(3)? as u8;
+    }
+    let code_lengths = Huffman::new(&code_lengths)?;
+
+    let mut lengths = vec![0; literal_count + distance_count];
+    let mut i = 0;
+    while i < lengths.len() {
+        let symbol = code_lengths.decode(reader)?;
+        let (value, repeat) = match symbol {
+            0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths[..i]
+                    .last()
+                    .ok_or_else(|| invalid("repeat with no previous length"))?;
+                (previous, 3 + reader.bits(2)? as usize)
+            }
+            17 => (0, 3 + reader.bits(3)? as usize),
+            _ => (0, 11 + reader.bits(7)? as usize),
+        };
+        if i + repeat > lengths.len() {
+            return Err(invalid("too many code lengths"));
+        }
+        lengths[i..i + repeat].fill(value);
+        i += repeat;
+    }
+    if lengths[256] == 0 {
+        return Err(invalid("missing end-of-block code"));
+    }
+
+    Ok((
+        Huffman::new(&lengths[..literal_count])?,
+        Huffman::new(&lengths[literal_count..])?,
+    ))
+}
+
+/// A canonical Huffman code, decoded a bit at a time by comparing against
+/// the first code of each length.
+struct Huffman {
+    /// Number of symbols with each code length.
+    counts: [u16; 16],
+    /// Symbols ordered by code length, then value.
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> io::Result<Huffman> {
+        let mut counts = [0u16; 16];
+        for &len in lengths {
+            counts[len as usize] += 1;
+        }
+        counts[0] = 0;
+
+        // Incomplete codes are allowed (e.g. a single distance code), but
+        // not ones with more codes than fit.
+        let mut left = 1i32;
+        for &count in &counts[1..] {
+            left = (left << 1) - count as i32;
+            if left < 0 {
+                return Err(invalid("over-subscribed Huffman code"));
+            }
+        }
+
+        let mut offsets = [0u16; 16];
+        for len in 1..15 {
+            offsets[len + 1] = offsets[len] + counts[len];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, &len) in lengths.iter().enumerate() {
+            if len != 0 {
+                symbols[offsets[len as usize] as usize] = symbol as u16;
+                offsets[len as usize] += 1;
+            }
+        }
+        Ok(Huffman { counts, symbols })
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> io::Result<u16> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for &count in &self.counts[1..] {
+            code |= reader.bits(1)? as i32;
+            let count = count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(invalid("invalid Huffman code"))
+    }
+}
+
+/// Reads bits least significant first, as DEFLATE packs them.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bits: u64,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> BitReader<'a> {
+        BitReader {
+            data,
+            pos: 0,
+            bits: 0,
+            count: 0,
+        }
+    }
+
+    fn bits(&mut self, n: u32) -> io::Result<u32> {
+        while self.count < n {
+            let byte = *self.data.get(self.pos).ok_or_else(truncated)?;
+            self.pos += 1;
+            self.bits |= (byte as u64) << self.count;
+            self.count += 8;
+        }
+        let value = (self.bits & ((1 << n) - 1)) as u32;
+        self.bits >>= n;
+        self.count -= n;
+        Ok(value)
+    }
+
+    /// Skips to the next byte boundary.
+    fn align(&mut self) {
+        let partial = self.count % 8;
+        self.bits >>= partial;
+        self.count -= partial;
+    }
+
+    /// Offset of the first byte not consumed, once aligned.
+    fn byte_pos(&mut self) -> usize {
+        self.align();
+        self.pos - (self.count / 8) as usize
+    }
+}
+
+/// CRC-32 of `data`, as checked against gzip trailers.
+pub fn crc32(data: &[u8]) -> u32 {
+    const TABLE: [u32; 256] = {
+        let mut table = [0; 256];
+        let mut i = 0;
+        while i < 256 {
+            let mut crc = i as u32;
+            let mut bit = 0;
+            while bit < 8 {
+                crc = if crc & 1 != 0 {
+                    0xedb8_8320 ^ (crc >> 1)
+                } else {
+                    crc >> 1
+                };
+                bit += 1;
+            }
+            table[i] = crc;
+            i += 1;
+        }
+        table
+    };
+
+    !data.iter().fold(!0u32, |crc, &byte| {
+        TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+    })
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, format!("gzip: {message}"))
+}
+
+fn truncated() -> io::Error {
+    io::Error::new(ErrorKind::UnexpectedEof, "gzip: unexpected end of data")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// "hello, world\n" in a stored block.
+    const STORED: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x04, 0x03, 0x01, 0x0d, 0x00, 0xf2, 0xff,
+        0x68, 0x65, 0x6c, 0x6c, 0x6f, 0x2c, 0x20, 0x77, 0x6f, 0x72, 0x6c, 0x64, 0x0a, 0x53, 0x74,
+        0x24, 0xf4, 0x0d, 0x00, 0x00, 0x00,
+    ];
+
+    /// "hello hello hello, world\n" in a fixed-Huffman block.
+    const FIXED: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0xcb, 0x48, 0xcd, 0xc9, 0xc9,
+        0x57, 0xc8, 0x40, 0x90, 0x3a, 0x0a, 0xe5, 0xf9, 0x45, 0x39, 0x29, 0x5c, 0x00, 0xd6, 0x70,
+        0xd7, 0x52, 0x19, 0x00, 0x00, 0x00,
+    ];
+
+    /// `log_lines()` in a dynamic-Huffman block.
+    const DYNAMIC: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x75, 0xd1, 0x4b, 0x0a, 0x02,
+        0x41, 0x0c, 0x84, 0xe1, 0xbd, 0xa7, 0xc8, 0x05, 0x84, 0x24, 0x9d, 0x7e, 0x79, 0x00, 0xc1,
+        0x8d, 0x5e, 0xc2, 0x5e, 0xb8, 0x50, 0x70, 0x5a, 0x3d, 0xbf, 0x83, 0x10, 0x21, 0x30, 0x05,
+        0xb5, 0xfc, 0xbf, 0x55, 0x29, 0xab, 0xed, 0x59, 0xd6, 0x91, 0xe8, 0x81, 0x79, 0x1d, 0x9d,
+        0xce, 0xc7, 0x0b, 0x2d, 0xe3, 0xf9, 0x1e, 0xf3, 0x45, 0x73, 0x2c, 0x9f, 0x71, 0xa5, 0xdb,
+        0x83, 0x98, 0xee, 0x73, 0xa7, 0xde, 0xab, 0xf7, 0x82, 0xfa, 0x1a, 0xfa, 0xe4, 0xbd, 0xa2,
+        0x5e, 0x2c, 0x00, 0x73, 0x90, 0x10, 0x50, 0x09, 0x20, 0x3b, 0x30, 0x08, 0x5a, 0x00, 0xc5,
+        0x41, 0x46, 0x20, 0xe5, 0x00, 0xaa, 0x83, 0x82, 0x80, 0x69, 0x00, 0xcd, 0x41, 0x85, 0xa0,
+        0x07, 0xd0, 0x1d, 0x34, 0x04, 0x72, 0x09, 0xe0, 0x7f, 0x5b, 0x47, 0xa0, 0xa4, 0xed, 0xdf,
+        0xe0, 0xcf, 0x95, 0xb7, 0x8f, 0xc3, 0x47, 0xff, 0x9e, 0xfe, 0x02, 0x61, 0xa3, 0x98, 0x3c,
+        0x4a, 0x02, 0x00, 0x00,
+    ];
+
+    /// "second\n" in a fixed-Huffman block.
+    const SECOND: &[u8] = &[
+        0x1f, 0x8b, 0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x03, 0x2b, 0x4e, 0x4d, 0xce, 0xcf,
+        0x4b, 0xe1, 0x02, 0x00, 0x7e, 0xc0, 0x0f, 0x06, 0x07, 0x00, 0x00, 0x00,
+    ];
+
+    fn log_lines() -> String {
+        (0..12)
+            .map(|i| {
+                format!(
+                    "2024-01-0{} 12:00:0{} INFO request served in {} ms\n",
+                    i % 9 + 1,
+                    i % 10,
+                    i * 7 % 100
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stored_block() {
+        assert_eq!(decompress(STORED).unwrap(), b"hello, world\n");
+    }
+
+    #[test]
+    fn fixed_huffman_block() {
+        assert_eq!(decompress(FIXED).unwrap(), b"hello hello hello, world\n");
+    }
+
+    #[test]
+    fn dynamic_huffman_block() {
+        assert_eq!(decompress(DYNAMIC).unwrap(), log_lines().as_bytes());
+    }
+
+    #[test]
+    fn multiple_members() {
+        let data = [STORED, SECOND].concat();
+        assert_eq!(decompress(&data).unwrap(), b"hello, world\nsecond\n");
+    }
+
+    #[test]
+    fn checksum_mismatch() {
+        let len = STORED.len();
+        for index in [len - 8, len - 1] {
+            let mut data = STORED.to_vec();
+            data[index] ^= 1;
+            let error = decompress(&data).unwrap_err();
+            assert_eq!(error.kind(), ErrorKind::InvalidData);
+            assert!(error.to_string().contains("checksum mismatch"));
+        }
+    }
+
+    #[test]
+    fn truncated_input() {
+        for data in [STORED, FIXED, DYNAMIC] {
+            for len in 0..data.len() {
+                assert!(decompress(&data[..len]).is_err(), "{len} bytes");
+            }
+        }
+    }
+
+    #[test]
+    fn corrupt_input_does_not_panic() {
+        for data in [STORED, FIXED, DYNAMIC] {
+            for index in 10..data.len() {
+                for flip in [0x01, 0x10, 0xff] {
+                    let mut data = data.to_vec();
+                    data[index] ^= flip;
+                    let _ = decompress(&data);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,141 @@
+//! The help overlay opened with `?` or `:help`, listing the keys and
+//! commands.
+
+use crate::keymap::{Action, Keymap};
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+
+/// Built-in commands, with their arguments, and what they do.
+pub const COMMANDS: &[(&str, &str, &str)] = &[
+    ("goto", "<n>", "go to line n"),
+    ("filter", "<pattern>", "show only matching lines"),
+    ("filter!", "<pattern>", "hide matching lines"),
+    (
+        "since",
+        "<time>",
+        "hide lines before a time, e.g. 10m or 2024-01-01T00:00",
+    ),
+    (
+        "since!",
+        "[time]",
+        "dim them instead; no time stops dimming",
+    ),
+    (
+        "set",
+        "<option>...",
+        "ic nu rnu levelcolor wrap ws json fold reltime mouse scb list",
+    ),
+    (
+        "set",
+        "no<option>",
+        "unset; <option>! toggles, <option>? shows it",
+    ),
+    ("set", "ts=<n>", "columns between tab stops"),
+    ("open", "<file>", "open another file"),
+    ("tabnew", "<file>", "open a file in a new tab"),
+    ("tabclose", "", "close the current tab"),
+    (
+        "split",
+        "[file]",
+        "show a file, or this one, in a second pane",
+    ),
+    ("vsplit", "[file]", "split side by side"),
+    ("only", "", "close the other pane"),
+    (
+        "columns",
+        "<key>,...",
+        "show keys of JSON/logfmt lines as columns",
+    ),
+    ("reload", "", "reread the file from disk"),
+    ("source", "<file>", "run a Lua script"),
+    (
+        "write",
+        "<file>",
+        "save the lines in view; write! overwrites",
+    ),
+    (
+        "stats",
+        "",
+        "count the lines in view by level, and their rate",
+    ),
+    (
+        "histogram",
+        "<pattern> [n]",
+        "chart matches per 1m, per n like 5s or 1h, or per n lines",
+    ),
+    ("help", "", "show this help"),
+    ("quit()", "", "quit"),
+];
+
+/// Draws the help over `area`, scrolled down by `scroll` lines, which is
+/// clamped to the help's length. The keys are those bound in `keymap`, and
+/// `user_commands` are the commands registered from Lua.
+pub fn render(
+    f: &mut Frame,
+    area: Rect,
+    scroll: &mut usize,
+    keymap: &Keymap,
+    user_commands: &[String],
+) {
+    let heading = Style::default().add_modifier(Modifier::BOLD);
+    let key_style = Style::default().fg(Color::Yellow);
+    let entry = |key: String, description: &str| {
+        Line::from(vec![
+            Span::styled(format!("  {key:<20} "), key_style),
+            Span::raw(description.to_string()),
+        ])
+    };
+
+    let mut lines = vec![Line::styled("Keys", heading)];
+    lines.extend(Action::ALL.iter().filter_map(|&action| {
+        let keys = keymap.keys_for(action);
+        (!keys.is_empty()).then(|| entry(keys.join(" "), action.description()))
+    }));
+    lines.push(entry(
+        "{count}".to_string(),
+        "repeat a motion the given times, e.g. 10j",
+    ));
+    lines.push(Line::default());
+    lines.push(Line::styled("Commands", heading));
+    lines.extend(
+        COMMANDS
+            .iter()
+            .map(|(name, args, description)| entry(format!(":{name} {args}"), description)),
+    );
+    lines.extend(
+        user_commands
+            .iter()
+            .map(|name| entry(format!(":{name}"), "defined in Lua")),
+    );
+    lines.push(entry(":<lua>".to_string(), "run a Lua chunk"));
+
+    let width = 72.min(area.width);
+    let height = (lines.len() as u16 + 2).min(area.height);
+    *scroll = (*scroll).min(
+        lines
+            .len()
+            .saturating_sub(height.saturating_sub(2) as usize),
+    );
+    let popup = Rect {
+        x: area.x + (area.width - width) / 2,
+        y: area.y + (area.height - height) / 2,
+        width,
+        height,
+    };
+    let block = Block::default()
+        .borders(Borders::ALL)
+        .title("Help")
+        .title_bottom(Line::from(" j/k to scroll, any other key to close ").right_aligned());
+    f.render_widget(Clear, popup);
+    f.render_widget(
+        Paragraph::new(lines)
+            .block(block)
+            .scroll((*scroll as u16, 0)),
+        popup,
+    );
+}
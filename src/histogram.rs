@@ -0,0 +1,108 @@
+//! The chart of matches per time bucket, or per range of lines, that
+//! `:histogram` shows.
+
+use crate::theme::Theme;
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::Style,
+    text::Line,
+    widgets::{Block, Borders, Clear, Paragraph, Sparkline},
+};
+
+/// Rows of the popup, borders included.
+const HEIGHT: u16 = 14;
+
+pub struct Histogram {
+    pub title: String,
+    /// Matches in each bucket, from the first with any to the last.
+    pub counts: Vec<u64>,
+    /// Where each bucket starts, e.g. a time or a line number.
+    pub labels: Vec<String>,
+}
+
+impl Histogram {
+    /// Draws the chart in the middle of `area`. Buckets are added up in
+    /// runs when there are more than columns.
+    pub fn render(&self, f: &mut Frame, area: Rect, theme: &Theme) {
+        let width = area.width.saturating_sub(4).max(20).min(area.width);
+        let height = HEIGHT.min(area.height);
+        let popup = Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + (area.height - height) / 2,
+            width,
+            height,
+        };
+        let merge = self
+            .counts
+            .len()
+            .div_ceil(width.saturating_sub(2).max(1) as usize)
+            .max(1);
+        let columns: Vec<u64> = self
+            .counts
+            .chunks(merge)
+            .map(|run| run.iter().sum())
+            .collect();
+        let mut title = format!("Histogram: {}", self.title);
+        if merge > 1 {
+            title.push_str(&format!(", {merge} buckets per column"));
+        }
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(title)
+            .title_bottom(Line::from(" any key to close ").right_aligned());
+        let inner = block.inner(popup);
+        f.render_widget(Clear, popup);
+        f.render_widget(block, popup);
+        if inner.height < 3 {
+            return;
+        }
+
+        let chart = Rect {
+            height: inner.height - 2,
+            ..inner
+        };
+        f.render_widget(
+            Sparkline::default()
+                .data(&columns)
+                .style(Style::default().fg(theme.match_bg)),
+            chart,
+        );
+
+        let (first, last) = match (self.labels.first(), self.labels.last()) {
+            (Some(first), Some(last)) => (first.as_str(), last.as_str()),
+            _ => ("", ""),
+        };
+        // The last label ends under the last column, if there's room.
+        let labels_width = first.chars().count() + last.chars().count();
+        let axis_width = columns
+            .len()
+            .max(labels_width + 1)
+            .min(inner.width as usize);
+        let gap = axis_width.saturating_sub(labels_width);
+        let axis = Line::styled(
+            format!("{first}{}{last}", " ".repeat(gap)),
+            Style::default().fg(theme.line_number),
+        );
+        let total: u64 = self.counts.iter().sum();
+        let summary = match columns
+            .iter()
+            .enumerate()
+            .max_by_key(|&(index, &count)| (count, usize::MAX - index))
+        {
+            Some((index, &most)) if most > 0 => format!(
+                "{total} matches, most in a column: {most} from {}",
+                self.labels[index * merge]
+            ),
+            _ => "no matches".to_string(),
+        };
+        f.render_widget(
+            Paragraph::new(vec![axis, Line::raw(summary)]),
+            Rect {
+                y: inner.y + inner.height - 2,
+                height: 2,
+                ..inner
+            },
+        );
+    }
+}
@@ -0,0 +1,52 @@
+//! Recall of previously entered prompt input with the up and down arrows.
+
+#[derive(Default)]
+pub struct History {
+    entries: Vec<String>,
+    /// Entry being shown, or `entries.len()` for the input being typed.
+    cursor: usize,
+    /// What was typed before recalling older entries, restored by moving
+    /// back past the newest one.
+    draft: String,
+}
+
+impl History {
+    /// Records `entry`, unless it's empty or repeats the last one, and
+    /// resets recall.
+    pub fn push(&mut self, entry: &str) {
+        if !entry.is_empty() && self.entries.last().map(String::as_str) != Some(entry) {
+            self.entries.push(entry.to_string());
+        }
+        self.reset();
+    }
+
+    /// Starts recall over from the newest entry, e.g. when the prompt is
+    /// opened.
+    pub fn reset(&mut self) {
+        self.cursor = self.entries.len();
+        self.draft.clear();
+    }
+
+    /// Steps back to the entry before the one shown, given the `current`
+    /// input. Returns `None` at the oldest entry.
+    pub fn previous(&mut self, current: &str) -> Option<&str> {
+        if self.cursor == 0 {
+            return None;
+        }
+        if self.cursor == self.entries.len() {
+            self.draft = current.to_string();
+        }
+        self.cursor -= 1;
+        Some(&self.entries[self.cursor])
+    }
+
+    /// Steps forward to the next entry, ending with the draft input.
+    /// Returns `None` if no older entry is shown.
+    pub fn next(&mut self) -> Option<&str> {
+        if self.cursor >= self.entries.len() {
+            return None;
+        }
+        self.cursor += 1;
+        Some(self.entries.get(self.cursor).unwrap_or(&self.draft))
+    }
+}
@@ -0,0 +1,365 @@
+//! A small JSON parser for structured log lines, and their summary for
+//! `:set json`.
+//!
+//! Only whole lines holding one value are parsed, so this favours
+//! simplicity over speed and keeps objects' keys in their original order.
+
+use std::{borrow::Cow, fmt};
+
+/// Nesting deeper than this is rejected rather than risking the stack.
+const MAX_DEPTH: usize = 128;
+
+/// Keys holding the timestamp, level and message of a log entry, in order
+/// of preference.
+const TIMESTAMP_KEYS: &[&str] = &["timestamp", "@timestamp", "time", "ts", "t"];
+const LEVEL_KEYS: &[&str] = &["level", "lvl", "severity", "loglevel", "log.level"];
+const MESSAGE_KEYS: &[&str] = &["message", "msg", "@message", "event"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    /// Kept as written, since logs often hold integers beyond `f64`.
+    Number(String),
+    String(String),
+    Array(Vec<Value>),
+    /// Members in their original order.
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    /// The value as shown to the user: strings without quotes, anything
+    /// else as compact JSON.
+    pub fn text(&self) -> Cow<'_, str> {
+        match self {
+            Value::String(text) => Cow::Borrowed(text),
+            value => Cow::Owned(value.to_string()),
+        }
+    }
+
+    /// The value as JSON indented by two spaces a level, with a member or
+    /// item per line.
+    pub fn pretty(&self) -> String {
+        let mut text = String::new();
+        self.write_pretty(&mut text, 0);
+        text
+    }
+
+    fn write_pretty(&self, text: &mut String, depth: usize) {
+        let indent = "  ".repeat(depth + 1);
+        match self {
+            Value::Array(items) if !items.is_empty() => {
+                text.push_str("[\n");
+                for (i, item) in items.iter().enumerate() {
+                    text.push_str(&indent);
+                    item.write_pretty(text, depth + 1);
+                    text.push_str(if i + 1 < items.len() { ",\n" } else { "\n" });
+                }
+                text.push_str(&indent[2..]);
+                text.push(']');
+            }
+            Value::Object(members) if !members.is_empty() => {
+                text.push_str("{\n");
+                for (i, (name, value)) in members.iter().enumerate() {
+                    text.push_str(&indent);
+                    text.push_str(&format!("{}: ", Value::String(name.clone())));
+                    value.write_pretty(text, depth + 1);
+                    text.push_str(if i + 1 < members.len() { ",\n" } else { "\n" });
+                }
+                text.push_str(&indent[2..]);
+                text.push('}');
+            }
+            value => text.push_str(&value.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    /// Writes the value as compact JSON.
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Null => f.write_str("null"),
+            Value::Bool(value) => write!(f, "{value}"),
+            Value::Number(number) => f.write_str(number),
+            Value::String(text) => write_string(f, text),
+            Value::Array(items) => {
+                f.write_str("[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                f.write_str("]")
+            }
+            Value::Object(members) => {
+                f.write_str("{")?;
+                for (i, (name, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        f.write_str(",")?;
+                    }
+                    write_string(f, name)?;
+                    write!(f, ":{value}")?;
+                }
+                f.write_str("}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter, text: &str) -> fmt::Result {
+    f.write_str("\"")?;
+    for c in text.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if c.is_control() => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{c}")?,
+        }
+    }
+    f.write_str("\"")
+}
+
+/// Parses `text` as a single JSON value, allowing surrounding whitespace.
+pub fn parse(text: &str) -> Option<Value> {
+    let mut parser = Parser {
+        bytes: text.as_bytes(),
+        pos: 0,
+    };
+    let value = parser.value(0)?;
+    parser.skip_whitespace();
+    (parser.pos == parser.bytes.len()).then_some(value)
+}
+
+/// Summarizes a JSON object log line as its timestamp, level and message,
+/// followed by its other members as `key=value`. Returns `None` for lines
+/// that aren't JSON objects.
+pub fn summarize(line: &str) -> Option<String> {
+    if !line.trim_start().starts_with('{') {
+        return None;
+    }
+    let Value::Object(members) = parse(line)? else {
+        return None;
+    };
+
+    let find = |keys: &[&str]| {
+        keys.iter()
+            .find_map(|key| members.iter().position(|(name, _)| name == key))
+    };
+    let timestamp = find(TIMESTAMP_KEYS);
+    let level = find(LEVEL_KEYS);
+    let message = find(MESSAGE_KEYS);
+
+    let mut summary = String::new();
+    if let Some(i) = timestamp {
+        summary.push_str(&members[i].1.text());
+        summary.push(' ');
+    }
+    if let Some(i) = level {
+        summary.push_str(&format!("{:<5} ", members[i].1.text().to_uppercase()));
+    }
+    if let Some(i) = message {
+        summary.push_str(&members[i].1.text());
+    }
+    for (i, (name, value)) in members.iter().enumerate() {
+        if [timestamp, level, message].contains(&Some(i)) {
+            continue;
+        }
+        if !summary.is_empty() && !summary.ends_with(' ') {
+            summary.push(' ');
+        }
+        summary.push_str(&format!("{name}={value}"));
+    }
+    Some(summary.trim_end().to_string())
+}
+
+struct Parser<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl Parser<'_> {
+    fn value(&mut self, depth: usize) -> Option<Value> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        self.skip_whitespace();
+        match *self.bytes.get(self.pos)? {
+            b'{' => self.object(depth),
+            b'[' => self.array(depth),
+            b'"' => self.string().map(Value::String),
+            b't' => self.literal("true", Value::Bool(true)),
+            b'f' => self.literal("false", Value::Bool(false)),
+            b'n' => self.literal("null", Value::Null),
+            b'-' | b'0'..=b'9' => self.number(),
+            _ => None,
+        }
+    }
+
+    fn object(&mut self, depth: usize) -> Option<Value> {
+        self.pos += 1;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.eat(b'}') {
+            return Some(Value::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.bytes.get(self.pos) != Some(&b'"') {
+                return None;
+            }
+            let name = self.string()?;
+            self.skip_whitespace();
+            if !self.eat(b':') {
+                return None;
+            }
+            members.push((name, self.value(depth + 1)?));
+            self.skip_whitespace();
+            if self.eat(b'}') {
+                return Some(Value::Object(members));
+            }
+            if !self.eat(b',') {
+                return None;
+            }
+        }
+    }
+
+    fn array(&mut self, depth: usize) -> Option<Value> {
+        self.pos += 1;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.eat(b']') {
+            return Some(Value::Array(items));
+        }
+        loop {
+            items.push(self.value(depth + 1)?);
+            self.skip_whitespace();
+            if self.eat(b']') {
+                return Some(Value::Array(items));
+            }
+            if !self.eat(b',') {
+                return None;
+            }
+        }
+    }
+
+    fn string(&mut self) -> Option<String> {
+        self.pos += 1;
+        let mut text = String::new();
+        loop {
+            // Copy runs of plain characters at once.
+            let run = self.bytes[self.pos..]
+                .iter()
+                .position(|&byte| byte == b'"' || byte == b'\\' || byte < 0x20)?;
+            // Runs end at ASCII bytes, so they're whole UTF-8 sequences.
+            text.push_str(std::str::from_utf8(&self.bytes[self.pos..self.pos + run]).ok()?);
+            self.pos += run;
+            match self.bytes[self.pos] {
+                b'"' => {
+                    self.pos += 1;
+                    return Some(text);
+                }
+                b'\\' => {
+                    self.pos += 1;
+                    let escape = *self.bytes.get(self.pos)?;
+                    self.pos += 1;
+                    text.push(match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => self.unicode_escape()?,
+                        _ => return None,
+                    });
+                }
+                // Unescaped control characters aren't allowed.
+                _ => return None,
+            }
+        }
+    }
+
+    /// Decodes the code point of a `\u` escape, whose `\u` was consumed,
+    /// combining surrogate pairs. Lone surrogates become U+FFFD.
+    fn unicode_escape(&mut self) -> Option<char> {
+        let high = self.hex4()?;
+        if !(0xd800..0xdc00).contains(&high) {
+            return Some(char::from_u32(high).unwrap_or(char::REPLACEMENT_CHARACTER));
+        }
+        if self.bytes[self.pos..].starts_with(b"\\u") {
+            self.pos += 2;
+            let low = self.hex4()?;
+            if (0xdc00..0xe000).contains(&low) {
+                let c = 0x10000 + ((high - 0xd800) << 10) + (low - 0xdc00);
+                return char::from_u32(c);
+            }
+        }
+        Some(char::REPLACEMENT_CHARACTER)
+    }
+
+    fn hex4(&mut self) -> Option<u32> {
+        let digits = std::str::from_utf8(self.bytes.get(self.pos..self.pos + 4)?).ok()?;
+        let value = u32::from_str_radix(digits, 16).ok()?;
+        self.pos += 4;
+        Some(value)
+    }
+
+    fn number(&mut self) -> Option<Value> {
+        let start = self.pos;
+        self.eat(b'-');
+        if !self.eat(b'0') && self.digits() == 0 {
+            return None;
+        }
+        if self.eat(b'.') && self.digits() == 0 {
+            return None;
+        }
+        if self.eat(b'e') || self.eat(b'E') {
+            if !self.eat(b'+') {
+                self.eat(b'-');
+            }
+            if self.digits() == 0 {
+                return None;
+            }
+        }
+        let number = std::str::from_utf8(&self.bytes[start..self.pos]).ok()?;
+        Some(Value::Number(number.to_string()))
+    }
+
+    /// Skips decimal digits, returning how many there were.
+    fn digits(&mut self) -> usize {
+        let count = self.bytes[self.pos..]
+            .iter()
+            .take_while(|byte| byte.is_ascii_digit())
+            .count();
+        self.pos += count;
+        count
+    }
+
+    fn literal(&mut self, word: &str, value: Value) -> Option<Value> {
+        if !self.bytes[self.pos..].starts_with(word.as_bytes()) {
+            return None;
+        }
+        self.pos += word.len();
+        Some(value)
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        let found = self.bytes.get(self.pos) == Some(&byte);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.bytes.get(self.pos) {
+            self.pos += 1;
+        }
+    }
+}
@@ -0,0 +1,415 @@
+//! Normal mode key bindings: the actions keys can trigger, their default
+//! keys, and overrides from the config's `keybindings`.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::{collections::HashMap, fmt, str::FromStr};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    ScrollDown,
+    ScrollUp,
+    ScrollLeft,
+    ScrollRight,
+    PageDown,
+    PageUp,
+    GotoTop,
+    GotoBottom,
+    GotoPercent,
+    ToggleFollow,
+    Search,
+    SearchNext,
+    SearchPrevious,
+    SearchWord,
+    SearchWordBackward,
+    FilterWord,
+    ClearFilters,
+    PopFilter,
+    CopyLine,
+    VisualMode,
+    ToggleBookmark,
+    NextBookmark,
+    PreviousBookmark,
+    NextError,
+    PreviousError,
+    NextWarning,
+    PreviousWarning,
+    SetMark,
+    JumpToMark,
+    ToggleEntry,
+    Reload,
+    NextTab,
+    PreviousTab,
+    FocusOtherPane,
+    Command,
+    RepeatCommand,
+    Palette,
+    Help,
+    Quit,
+}
+
+impl Action {
+    /// Every action, in the order the help lists them.
+    pub const ALL: &[Action] = &[
+        Action::ScrollDown,
+        Action::ScrollUp,
+        Action::ScrollLeft,
+        Action::ScrollRight,
+        Action::PageDown,
+        Action::PageUp,
+        Action::GotoTop,
+        Action::GotoBottom,
+        Action::GotoPercent,
+        Action::ToggleFollow,
+        Action::Search,
+        Action::SearchNext,
+        Action::SearchPrevious,
+        Action::SearchWord,
+        Action::SearchWordBackward,
+        Action::FilterWord,
+        Action::ClearFilters,
+        Action::PopFilter,
+        Action::CopyLine,
+        Action::VisualMode,
+        Action::ToggleBookmark,
+        Action::NextBookmark,
+        Action::PreviousBookmark,
+        Action::NextError,
+        Action::PreviousError,
+        Action::NextWarning,
+        Action::PreviousWarning,
+        Action::SetMark,
+        Action::JumpToMark,
+        Action::ToggleEntry,
+        Action::Reload,
+        Action::NextTab,
+        Action::PreviousTab,
+        Action::FocusOtherPane,
+        Action::Command,
+        Action::RepeatCommand,
+        Action::Palette,
+        Action::Help,
+        Action::Quit,
+    ];
+
+    /// Name used in the config.
+    pub fn name(self) -> &'static str {
+        match self {
+            Action::ScrollDown => "scroll_down",
+            Action::ScrollUp => "scroll_up",
+            Action::ScrollLeft => "scroll_left",
+            Action::ScrollRight => "scroll_right",
+            Action::PageDown => "page_down",
+            Action::PageUp => "page_up",
+            Action::GotoTop => "goto_top",
+            Action::GotoBottom => "goto_bottom",
+            Action::GotoPercent => "goto_percent",
+            Action::ToggleFollow => "toggle_follow",
+            Action::Search => "search",
+            Action::SearchNext => "search_next",
+            Action::SearchPrevious => "search_previous",
+            Action::SearchWord => "search_word",
+            Action::SearchWordBackward => "search_word_backward",
+            Action::FilterWord => "filter_word",
+            Action::ClearFilters => "clear_filters",
+            Action::PopFilter => "pop_filter",
+            Action::CopyLine => "copy_line",
+            Action::VisualMode => "visual_mode",
+            Action::ToggleBookmark => "toggle_bookmark",
+            Action::NextBookmark => "next_bookmark",
+            Action::PreviousBookmark => "previous_bookmark",
+            Action::NextError => "next_error",
+            Action::PreviousError => "previous_error",
+            Action::NextWarning => "next_warning",
+            Action::PreviousWarning => "previous_warning",
+            Action::SetMark => "set_mark",
+            Action::JumpToMark => "jump_to_mark",
+            Action::ToggleEntry => "toggle_entry",
+            Action::Reload => "reload",
+            Action::NextTab => "next_tab",
+            Action::PreviousTab => "previous_tab",
+            Action::FocusOtherPane => "focus_other_pane",
+            Action::Command => "command",
+            Action::RepeatCommand => "repeat_command",
+            Action::Palette => "palette",
+            Action::Help => "help",
+            Action::Quit => "quit",
+        }
+    }
+
+    pub fn description(self) -> &'static str {
+        match self {
+            Action::ScrollDown => "move down a line",
+            Action::ScrollUp => "move up a line",
+            Action::ScrollLeft => "scroll left",
+            Action::ScrollRight => "scroll right",
+            Action::PageDown => "page down",
+            Action::PageUp => "page up",
+            Action::GotoTop => "go to the top, or line {count}",
+            Action::GotoBottom => "go to the bottom, or line {count}",
+            Action::GotoPercent => "go {count} percent of the way through the file",
+            Action::ToggleFollow => "toggle following the end of the file",
+            Action::Search => "search (↑ ↓ recall earlier searches)",
+            Action::SearchNext => "next match",
+            Action::SearchPrevious => "previous match",
+            Action::SearchWord => "search for the word at the left of the view",
+            Action::SearchWordBackward => "search backward for that word",
+            Action::FilterWord => "filter on that word",
+            Action::ClearFilters => "clear all filters",
+            Action::PopFilter => "remove the last filter",
+            Action::CopyLine => "copy the current line",
+            Action::VisualMode => "select lines to copy with j k, then y",
+            Action::ToggleBookmark => "toggle a bookmark on the current line",
+            Action::NextBookmark => "next bookmark",
+            Action::PreviousBookmark => "previous bookmark",
+            Action::NextError => "next error or fatal line",
+            Action::PreviousError => "previous error or fatal line",
+            Action::NextWarning => "next warning line",
+            Action::PreviousWarning => "previous warning line",
+            Action::SetMark => "set mark {a-z} on the current line",
+            Action::JumpToMark => "jump to mark {a-z}",
+            Action::ToggleEntry => "show the whole line, or fold / unfold an entry with :set fold",
+            Action::Reload => "reload the file",
+            Action::NextTab => "next tab, or tab {count}",
+            Action::PreviousTab => "previous tab, or {count} tabs back",
+            Action::FocusOtherPane => "switch to the other pane of a split",
+            Action::Command => "command (Tab completes, ↑ ↓ recall)",
+            Action::RepeatCommand => "repeat the last command",
+            Action::Palette => "pick a command or recent file, fuzzy-matching what's typed",
+            Action::Help => "show this help",
+            Action::Quit => "quit",
+        }
+    }
+}
+
+impl FromStr for Action {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Action, String> {
+        Action::ALL
+            .iter()
+            .copied()
+            .find(|action| action.name() == name)
+            .ok_or_else(|| format!("unknown action '{name}'"))
+    }
+}
+
+/// Default bindings, in the order their keys are listed in the help.
+const DEFAULT_BINDINGS: &[(&str, Action)] = &[
+    ("j", Action::ScrollDown),
+    ("down", Action::ScrollDown),
+    ("k", Action::ScrollUp),
+    ("up", Action::ScrollUp),
+    ("h", Action::ScrollLeft),
+    ("left", Action::ScrollLeft),
+    ("l", Action::ScrollRight),
+    ("right", Action::ScrollRight),
+    ("ctrl-f", Action::PageDown),
+    ("pagedown", Action::PageDown),
+    ("ctrl-b", Action::PageUp),
+    ("pageup", Action::PageUp),
+    ("gg", Action::GotoTop),
+    ("G", Action::GotoBottom),
+    ("%", Action::GotoPercent),
+    ("F", Action::ToggleFollow),
+    ("/", Action::Search),
+    ("n", Action::SearchNext),
+    ("N", Action::SearchPrevious),
+    ("*", Action::SearchWord),
+    ("#", Action::SearchWordBackward),
+    ("&", Action::FilterWord),
+    ("\\", Action::ClearFilters),
+    ("|", Action::PopFilter),
+    ("y", Action::CopyLine),
+    ("V", Action::VisualMode),
+    ("M", Action::ToggleBookmark),
+    ("]'", Action::NextBookmark),
+    ("['", Action::PreviousBookmark),
+    ("]e", Action::NextError),
+    ("[e", Action::PreviousError),
+    ("]w", Action::NextWarning),
+    ("[w", Action::PreviousWarning),
+    ("m", Action::SetMark),
+    ("`", Action::JumpToMark),
+    ("'", Action::JumpToMark),
+    ("enter", Action::ToggleEntry),
+    ("r", Action::Reload),
+    ("gt", Action::NextTab),
+    ("gT", Action::PreviousTab),
+    ("ctrl-w", Action::FocusOtherPane),
+    (":", Action::Command),
+    (".", Action::RepeatCommand),
+    ("ctrl-p", Action::Palette),
+    ("?", Action::Help),
+    ("q", Action::Quit),
+];
+
+/// A key press, ignoring modifiers other than Ctrl and Alt; Shift is part
+/// of the character typed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Key {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl From<KeyEvent> for Key {
+    fn from(event: KeyEvent) -> Key {
+        Key {
+            code: event.code,
+            modifiers: event.modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT),
+        }
+    }
+}
+
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            f.write_str("ctrl-")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            f.write_str("alt-")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => f.write_str("space"),
+            KeyCode::Char(c) => write!(f, "{c}"),
+            code => match NAMED_KEYS.iter().find(|(_, named)| *named == code) {
+                Some((name, _)) => f.write_str(name),
+                None => write!(f, "{code:?}"),
+            },
+        }
+    }
+}
+
+const NAMED_KEYS: &[(&str, KeyCode)] = &[
+    ("enter", KeyCode::Enter),
+    ("esc", KeyCode::Esc),
+    ("tab", KeyCode::Tab),
+    ("backspace", KeyCode::Backspace),
+    ("delete", KeyCode::Delete),
+    ("insert", KeyCode::Insert),
+    ("up", KeyCode::Up),
+    ("down", KeyCode::Down),
+    ("left", KeyCode::Left),
+    ("right", KeyCode::Right),
+    ("home", KeyCode::Home),
+    ("end", KeyCode::End),
+    ("pageup", KeyCode::PageUp),
+    ("pagedown", KeyCode::PageDown),
+    ("space", KeyCode::Char(' ')),
+];
+
+/// Parses a key sequence: a named key (`enter`, `pagedown`, ...) or a
+/// single character, either with `ctrl-` and `alt-` prefixes, or else a
+/// run of plain characters typed in turn, like `gg`.
+fn parse_keys(name: &str) -> Result<Vec<Key>, String> {
+    let mut modifiers = KeyModifiers::NONE;
+    let mut rest = name;
+    loop {
+        if let Some(after) = rest.strip_prefix("ctrl-").filter(|after| !after.is_empty()) {
+            modifiers |= KeyModifiers::CONTROL;
+            rest = after;
+        } else if let Some(after) = rest.strip_prefix("alt-").filter(|after| !after.is_empty()) {
+            modifiers |= KeyModifiers::ALT;
+            rest = after;
+        } else {
+            break;
+        }
+    }
+
+    if let Some((_, code)) = NAMED_KEYS.iter().find(|(key, _)| *key == rest) {
+        return Ok(vec![Key {
+            code: *code,
+            modifiers,
+        }]);
+    }
+    let mut chars = rest.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) => Ok(vec![Key {
+            code: KeyCode::Char(c),
+            modifiers,
+        }]),
+        (Some(_), Some(_)) if modifiers.is_empty() => Ok(rest
+            .chars()
+            .map(|c| Key {
+                code: KeyCode::Char(c),
+                modifiers,
+            })
+            .collect()),
+        _ => Err(format!("invalid key '{name}'")),
+    }
+}
+
+/// What the keys typed so far amount to.
+pub enum Lookup {
+    Action(Action),
+    /// The start of a longer binding; wait for more keys.
+    Prefix,
+    Unbound,
+}
+
+pub struct Keymap {
+    bindings: HashMap<Vec<Key>, Action>,
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        let mut keymap = Keymap {
+            bindings: HashMap::new(),
+        };
+        for (keys, action) in DEFAULT_BINDINGS {
+            keymap.bindings.insert(
+                parse_keys(keys).expect("default bindings are valid"),
+                *action,
+            );
+        }
+        keymap
+    }
+}
+
+impl Keymap {
+    /// Binds `keys` to the action named `action`, replacing any binding
+    /// they had. The action `none` unbinds them.
+    pub fn bind(&mut self, keys: &str, action: &str) -> Result<(), String> {
+        let keys = parse_keys(keys)?;
+        if action == "none" {
+            self.bindings.remove(&keys);
+        } else {
+            self.bindings.insert(keys, action.parse()?);
+        }
+        Ok(())
+    }
+
+    pub fn lookup(&self, keys: &[Key]) -> Lookup {
+        if let Some(action) = self.bindings.get(keys) {
+            return Lookup::Action(*action);
+        }
+        if self
+            .bindings
+            .keys()
+            .any(|bound| bound.len() > keys.len() && bound.starts_with(keys))
+        {
+            Lookup::Prefix
+        } else {
+            Lookup::Unbound
+        }
+    }
+
+    /// The key sequences bound to `action`, defaults first, as written in
+    /// the config.
+    pub fn keys_for(&self, action: Action) -> Vec<String> {
+        let mut keys: Vec<(usize, String)> = self
+            .bindings
+            .iter()
+            .filter(|(_, bound)| **bound == action)
+            .map(|(keys, _)| {
+                let name: String = keys.iter().map(Key::to_string).collect();
+                let order = DEFAULT_BINDINGS
+                    .iter()
+                    .position(|(default, _)| *default == name)
+                    .unwrap_or(usize::MAX);
+                (order, name)
+            })
+            .collect();
+        keys.sort();
+        keys.into_iter().map(|(_, name)| name).collect()
+    }
+}
@@ -0,0 +1,66 @@
+//! Heuristic detection of a log line's severity level.
+
+use crate::theme::Theme;
+use ratatui::style::Style;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+    Fatal,
+}
+
+/// Keywords recognised as levels, compared case-insensitively against whole
+/// words.
+const KEYWORDS: &[(&str, Level)] = &[
+    ("TRACE", Level::Trace),
+    ("DEBUG", Level::Debug),
+    ("INFO", Level::Info),
+    ("WARN", Level::Warn),
+    ("WARNING", Level::Warn),
+    ("ERROR", Level::Error),
+    ("ERR", Level::Error),
+    ("FATAL", Level::Fatal),
+    ("CRITICAL", Level::Fatal),
+    ("PANIC", Level::Fatal),
+];
+
+impl Level {
+    /// Returns the level named by the first level keyword in `line`, so a
+    /// message mentioning "error" after an `INFO` tag stays info.
+    pub fn detect(line: &str) -> Option<Level> {
+        line.split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|word| (3..=8).contains(&word.len()))
+            .find_map(|word| {
+                KEYWORDS
+                    .iter()
+                    .find(|(keyword, _)| keyword.eq_ignore_ascii_case(word))
+                    .map(|&(_, level)| level)
+            })
+    }
+
+    /// Lowercase name, for messages.
+    pub fn name(self) -> &'static str {
+        match self {
+            Level::Trace => "trace",
+            Level::Debug => "debug",
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+            Level::Fatal => "fatal",
+        }
+    }
+
+    /// Base style for lines of this level when level coloring is on.
+    pub fn style(self, theme: &Theme) -> Style {
+        match self {
+            Level::Fatal | Level::Error => Style::default().fg(theme.error),
+            Level::Warn => Style::default().fg(theme.warn),
+            Level::Info => Style::default(),
+            Level::Debug | Level::Trace => Style::default().fg(theme.debug),
+        }
+    }
+}
@@ -0,0 +1,66 @@
+//! Parsing of logfmt lines: `level=info msg="a message" ts=...`.
+
+/// Parses `line` into its key/value pairs, with quoted values unescaped.
+/// Bare keys are flags with empty values. Returns `None` unless the whole
+/// line is logfmt with at least one `key=value` pair.
+pub fn parse(line: &str) -> Option<Vec<(String, String)>> {
+    let mut pairs = Vec::new();
+    let mut has_value = false;
+    let mut rest = line.trim_start();
+    while !rest.is_empty() {
+        let key_len = rest
+            .find(|c: char| c == '=' || c == '"' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        if key_len == 0 {
+            return None;
+        }
+        let key = rest[..key_len].to_string();
+        rest = &rest[key_len..];
+
+        let value = match rest.strip_prefix('=') {
+            Some(after) => {
+                has_value = true;
+                let (value, after) = match after.strip_prefix('"') {
+                    Some(quoted) => unquote(quoted)?,
+                    None => {
+                        let end = after.find(char::is_whitespace).unwrap_or(after.len());
+                        let (value, after) = after.split_at(end);
+                        if value.contains('"') {
+                            return None;
+                        }
+                        (value.to_string(), after)
+                    }
+                };
+                rest = after;
+                value
+            }
+            None => String::new(),
+        };
+        if !rest.is_empty() && !rest.starts_with(char::is_whitespace) {
+            return None;
+        }
+        pairs.push((key, value));
+        rest = rest.trim_start();
+    }
+    has_value.then_some(pairs)
+}
+
+/// Reads a quoted value up to its closing quote, whose opening quote was
+/// already consumed. Returns the value and the text after the quote.
+fn unquote(text: &str) -> Option<(String, &str)> {
+    let mut value = String::new();
+    let mut chars = text.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, &text[i + 1..])),
+            '\\' => match chars.next()?.1 {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                'r' => value.push('\r'),
+                escaped => value.push(escaped),
+            },
+            c => value.push(c),
+        }
+    }
+    None
+}
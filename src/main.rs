@@ -1,42 +1,232 @@
+mod ansi;
+mod clipboard;
+mod columns;
+mod content;
+mod detail;
+mod encoding;
+mod filetype;
+mod gzip;
+mod help;
+mod histogram;
+mod history;
+mod input;
+mod json;
+mod keymap;
+mod level;
+mod logfmt;
+mod mmap;
+mod options;
+mod palette;
+mod regex;
+mod script;
+mod session;
+mod stats;
+mod syslog;
+mod theme;
+mod timestamp;
+mod watch;
+
 use clap::Parser;
+use content::{Content, Loader};
 use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEventKind},
+    cursor,
+    event::{
+        self, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
+        Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers, MouseButton, MouseEvent,
+        MouseEventKind,
+    },
     execute,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
-use mlua::Lua;
+use history::History;
+use keymap::{Action, Keymap, Lookup};
+use level::Level;
+use mlua::{Function, Lua, RegistryKey};
 use ratatui::{
     Terminal,
     backend::CrosstermBackend,
-    layout::{Constraint, Direction, Layout},
-    style::{Color, Style},
-    text::Span,
-    widgets::{Block, Borders, List, ListItem, Paragraph},
+    layout::{Constraint, Direction, Layout, Margin, Position, Rect},
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{
+        Block, Borders, List, ListItem, Paragraph, Scrollbar, ScrollbarOrientation, ScrollbarState,
+        Tabs,
+    },
 };
+use regex::{Regex, RegexBuilder};
 use serde::{Deserialize, Serialize};
-use std::{error::Error, fs, io, path::PathBuf};
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashMap, HashSet},
+    error::Error,
+    fmt,
+    fs::{self, File},
+    io::{self, BufWriter, IsTerminal, Read, Seek, SeekFrom, Write},
+    mem, panic,
+    path::{Path, PathBuf},
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+/// How long the main loop waits for a key before checking for file changes.
+const TICK_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Shortest time between redraws, about 60 per second.
+const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+
+const DEFAULT_TAB_WIDTH: usize = 8;
+
+/// Columns moved by each horizontal scroll key press.
+const HORIZONTAL_SCROLL_STEP: usize = 4;
+
+/// Backgrounds of the capture groups within a search match, in turn.
+const GROUP_COLORS: [Color; 4] = [
+    Color::LightCyan,
+    Color::LightGreen,
+    Color::LightMagenta,
+    Color::LightBlue,
+];
+
+/// Most time buckets `:histogram` counts in.
+const MAX_HISTOGRAM_BUCKETS: i64 = 100_000;
+
+/// Lines moved by each turn of the mouse wheel.
+const MOUSE_SCROLL_LINES: usize = 3;
+
+/// Columns of the `:set reltime` gutter, e.g. `+59m59s` and a space.
+const RELTIME_WIDTH: usize = 9;
+
+/// Lines searched above the screen for the timestamp the first line's is
+/// compared with.
+const RELTIME_LOOKBACK: usize = 1000;
+
+/// Init script loaded when the config doesn't name one, relative to `~`.
+const DEFAULT_INIT_SCRIPT: &str = ".config/logview/init.lua";
+
+/// Directory of Lua modules for `require`, in the home directory.
+const DEFAULT_LUA_DIR: &str = ".config/logview/lua";
 
 #[derive(Parser)]
 #[command(name = "logview")]
 #[command(about = "A terminal-based log file viewer with Lua scripting")]
 struct Args {
-    #[arg(help = "Log file to view")]
-    file: Option<PathBuf>,
+    #[arg(help = "Log files to view, each in a tab")]
+    files: Vec<PathBuf>,
+    #[arg(
+        long,
+        help = "Memory map the file instead of reading lines on demand (it must not be truncated while open)"
+    )]
+    mmap: bool,
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Encoding of the file: utf-8, utf-16le, utf-16be, latin1 or windows-1252 (default: from its byte order mark, else utf-8)"
+    )]
+    encoding: Option<encoding::Encoding>,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Load only the last N lines of each file, like tail -n"
+    )]
+    tail: Option<usize>,
+    #[arg(
+        long,
+        help = "Quit on q without asking, even when following or filtering"
+    )]
+    no_confirm: bool,
+    #[arg(
+        long,
+        value_name = "PATH",
+        help = "Config file to use instead of ~/.logview.yml"
+    )]
+    config: Option<PathBuf>,
+    #[arg(
+        long,
+        help = "Don't restore the position, bookmarks and marks from the last time the file was viewed"
+    )]
+    no_restore: bool,
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        help = "Format of the lines: syslog or plain (default: syslog if the first lines are)"
+    )]
+    format: Option<syslog::Format>,
+    #[arg(
+        long,
+        value_name = "NAME",
+        help = "Apply the config's filetypes profile NAME instead of choosing one by extension or first line"
+    )]
+    filetype: Option<String>,
+    #[arg(
+        long,
+        help = "Run Lua scripts without access to files or commands: only the string, math and table libraries"
+    )]
+    safe: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 struct Config {
-    // Empty for now, can be extended later
-}
-
-impl Default for Config {
-    fn default() -> Self {
-        Config {}
-    }
+    /// Lua script run at startup. Defaults to `~/.config/logview/init.lua`
+    /// when that file exists.
+    #[serde(default)]
+    init_script: Option<PathBuf>,
+    /// Directories `require` searches for Lua modules, before
+    /// `~/.config/logview/lua`.
+    #[serde(default)]
+    lua_path: Vec<PathBuf>,
+    /// Run Lua scripts with only the string, math and table libraries, as
+    /// with `--safe`.
+    #[serde(default)]
+    safe: bool,
+    /// Pattern for lines continuing the previous entry with `:set fold`,
+    /// besides indented lines and lines starting with `at `.
+    #[serde(default)]
+    continuation: Option<String>,
+    /// Normal mode keys mapped to action names, e.g. `ctrl-d: page_down`,
+    /// overriding the defaults. The action `none` unbinds a key.
+    #[serde(default)]
+    keybindings: HashMap<String, String>,
+    #[serde(default)]
+    theme: theme::ThemeConfig,
+    /// Start following the end of the file.
+    #[serde(default)]
+    follow_on_start: bool,
+    #[serde(default)]
+    wrap: bool,
+    #[serde(default)]
+    show_line_numbers: bool,
+    /// Color lines by their log level.
+    #[serde(default)]
+    level_coloring: bool,
+    /// Columns between tab stops, 8 by default.
+    #[serde(default)]
+    tab_width: Option<usize>,
+    /// Let `n` and `N` wrap around the ends of the file, as by default.
+    #[serde(default)]
+    wrapscan: Option<bool>,
+    /// Format of the timestamps lines start with for `:set reltime`, e.g.
+    /// `%d/%m/%Y %H:%M:%S`. ISO 8601 and syslog timestamps are recognised
+    /// by default.
+    #[serde(default)]
+    timestamp_format: Option<String>,
+    /// Profiles of options applied to the files they match, by name.
+    #[serde(default)]
+    filetypes: BTreeMap<String, filetype::FileType>,
 }
 
 impl Config {
-    fn load() -> Result<Config, Box<dyn Error>> {
+    /// Loads the config from `path`, which must exist and be valid, or else
+    /// from `~/.logview.yml`, which is (re)written with the defaults if
+    /// missing or invalid.
+    fn load(path: Option<&Path>) -> Result<Config, Box<dyn Error>> {
+        if let Some(path) = path {
+            let contents = fs::read_to_string(path)
+                .map_err(|err| format!("config {}: {err}", path.display()))?;
+            return serde_yaml::from_str(&contents)
+                .map_err(|err| format!("config {}: {err}", path.display()).into());
+        }
+
         let config_path = dirs::home_dir()
             .ok_or("Could not find home directory")?
             .join(".logview.yml");
@@ -61,122 +251,3324 @@ impl Config {
     }
 }
 
+/// A line predicate from `:filter` (keep matches) or `:filter!` (drop
+/// matches).
+enum Filter {
+    Pattern {
+        regex: Regex,
+        invert: bool,
+    },
+    /// Hides lines timestamped before `cutoff`, in milliseconds since the
+    /// Unix epoch, given as `text`. Lines without a timestamp are kept.
+    Since {
+        cutoff: i64,
+        text: String,
+    },
+}
+
+impl Filter {
+    fn keeps(&self, line: &str, timestamps: &timestamp::Parser) -> bool {
+        match self {
+            Filter::Pattern { regex, invert } => regex.is_match(line) != *invert,
+            Filter::Since { cutoff, .. } => {
+                timestamps.parse(line).is_none_or(|time| time >= *cutoff)
+            }
+        }
+    }
+}
+
+impl fmt::Display for Filter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Filter::Pattern {
+                regex,
+                invert: true,
+            } => write!(f, "!{}", regex.as_str()),
+            Filter::Pattern {
+                regex,
+                invert: false,
+            } => f.write_str(regex.as_str()),
+            Filter::Since { text, .. } => write!(f, "since {text}"),
+        }
+    }
+}
+
+/// A message shown in the status bar until the next key press.
+struct StatusMessage {
+    text: String,
+    is_error: bool,
+}
+
+impl StatusMessage {
+    fn info(text: impl Into<String>) -> StatusMessage {
+        StatusMessage {
+            text: text.into(),
+            is_error: false,
+        }
+    }
+
+    fn error(text: impl Into<String>) -> StatusMessage {
+        StatusMessage {
+            text: text.into(),
+            is_error: true,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 enum InputMode {
     Normal,
     Command,
+    Search,
+    /// Selecting a range of lines, from `visual_anchor` to `visual_cursor`.
+    Visual,
+}
+
+impl fmt::Display for InputMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            InputMode::Normal => "NORMAL",
+            InputMode::Command => "COMMAND",
+            InputMode::Search => "SEARCH",
+            InputMode::Visual => "VISUAL",
+        })
+    }
+}
+
+/// Candidates for the command line offered by Tab, cycled through on
+/// repeated presses.
+struct Completion {
+    /// The command line as typed, restored after the last candidate.
+    original: String,
+    candidates: Vec<String>,
+    /// Candidate shown, or `candidates.len()` for the original.
+    index: usize,
+}
+
+/// Lines styled for the last frame, so the ones still on screen aren't
+/// parsed and highlighted again. They're only reused while `key` matches.
+#[derive(Default)]
+struct StyleCache {
+    key: Option<StyleKey>,
+    /// Styled lines by content index, with tabs expanded.
+    lines: HashMap<usize, Line<'static>>,
+}
+
+/// The lines matching the search, counted for the status bar as they're
+/// first shown and kept while the query stays the same.
+#[derive(Default)]
+struct MatchCount {
+    /// Pattern of the search, and whether it ignores case.
+    query: Option<(String, bool)>,
+    /// Content indices of the matching lines, in order.
+    lines: Vec<usize>,
+    /// Content lines scanned so far.
+    scanned: usize,
+}
+
+/// What the styling of a line depends on besides its text.
+#[derive(PartialEq)]
+struct StyleKey {
+    /// Pattern of the search, and whether it ignores case.
+    search: Option<(String, bool)>,
+    highlights: Vec<(String, Color)>,
+    level_coloring: bool,
+    syslog: bool,
+    json: bool,
+    /// Keys and widths of the columns.
+    columns: Option<(Vec<String>, Vec<usize>)>,
+    tab_width: usize,
+    list: bool,
+    dim_before: Option<i64>,
+}
+
+/// A file open in a tab. The active tab's state lives in the `App` fields
+/// of the same names, some of them shared with the Lua API, and is swapped
+/// with its `Buffer` when switching tabs.
+struct Buffer {
+    content: Content,
+    file_path: Option<PathBuf>,
+    watcher: Option<watch::Watcher>,
+    loading: Option<Loading>,
+    following: bool,
+    new_lines: Option<usize>,
+    scroll_offset: usize,
+    cursor_line: usize,
+    horizontal_offset: usize,
+    visible: Option<Vec<usize>>,
+    filters: Vec<Filter>,
+    folding: bool,
+    expanded: HashSet<usize>,
+    bookmarks: Vec<usize>,
+    marks: HashMap<char, usize>,
+    last_search: Option<String>,
+    search_regex: Option<Regex>,
+    style_cache: StyleCache,
+    match_count: MatchCount,
+    syslog: Option<bool>,
+    filetype_applied: bool,
+    dim_before: Option<i64>,
+}
+
+impl Buffer {
+    fn new(
+        content: Content,
+        file_path: Option<PathBuf>,
+        loading: Option<Loading>,
+        following: bool,
+    ) -> Buffer {
+        Buffer {
+            content,
+            watcher: file_path.clone().map(watch::Watcher::new),
+            file_path,
+            loading,
+            following,
+            new_lines: None,
+            scroll_offset: 0,
+            cursor_line: 0,
+            horizontal_offset: 0,
+            visible: None,
+            filters: Vec::new(),
+            folding: false,
+            expanded: HashSet::new(),
+            bookmarks: Vec::new(),
+            marks: HashMap::new(),
+            last_search: None,
+            search_regex: None,
+            style_cache: StyleCache::default(),
+            match_count: MatchCount::default(),
+            syslog: None,
+            filetype_applied: false,
+            dim_before: None,
+        }
+    }
+
+    /// A buffer loading the file at `path` in the background.
+    fn open(path: PathBuf, load_options: content::Options, following: bool) -> io::Result<Buffer> {
+        let file = File::open(&path)?;
+        let loading = Loading {
+            loader: Loader::spawn(path.clone(), load_options),
+            kind: LoadKind::Initial,
+            pending_change: None,
+        };
+        Ok(Buffer::new(
+            Content::indexed(file),
+            Some(path),
+            Some(loading),
+            following,
+        ))
+    }
+}
+
+/// Where a file opens: in place of the current one, or in a new tab.
+#[derive(Debug, Clone, Copy)]
+enum OpenIn {
+    Current,
+    NewTab,
+}
+
+/// What a pane of a split shows: a tab and the position in it.
+#[derive(Debug, Clone, Copy)]
+struct Pane {
+    buffer: usize,
+    scroll_offset: usize,
+    cursor_line: usize,
+    horizontal_offset: usize,
+}
+
+/// Two panes side by side or one above the other. The focused pane is the
+/// active tab, whose position is held in the fields of `App`.
+struct Split {
+    direction: Direction,
+    /// The pane that isn't focused.
+    other: Pane,
+    /// Which of the two areas, 0 or 1, the focused pane is drawn in.
+    focused: usize,
+}
+
+/// A file being loaded in the background.
+struct Loading {
+    loader: Loader,
+    kind: LoadKind,
+    /// The biggest change the watcher reported meanwhile, handled once the
+    /// load is done.
+    pending_change: Option<watch::Change>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LoadKind {
+    /// The file given on the command line.
+    Initial,
+    /// `:open`, which switches to another file.
+    Open,
+    /// `r` or `:reload`.
+    Reload,
+    /// The watcher saw the file rewritten.
+    Refresh,
 }
 
 struct App {
-    content: Vec<String>,
+    /// Shared with the Lua API, see `script::Shared`.
+    content: Rc<RefCell<Content>>,
+    /// Every tab; the active one's state is held in the fields of `App`.
+    buffers: Vec<Buffer>,
+    active: usize,
+    split: Option<Split>,
+    file_path: Option<PathBuf>,
+    /// Reports changes to `file_path` made by other programs.
+    watcher: Option<watch::Watcher>,
+    loading: Option<Loading>,
+    load_options: content::Options,
+    following: bool,
+    /// While following, after scrolling away from the end: the lines
+    /// appended since, which no longer scroll the view.
+    new_lines: Option<usize>,
+    /// Shared with the Lua API, see `script::Shared`.
+    scroll_offset: Rc<Cell<usize>>,
+    /// View position of the current line, which is highlighted and kept on
+    /// screen. Shared with the Lua API, see `script::Shared`.
+    cursor_line: Rc<Cell<usize>>,
+    viewport_height: usize,
+    /// Columns available for line text, excluding borders and the gutter.
+    viewport_width: usize,
+    /// Columns scrolled off to the left. Ignored when wrapping.
+    horizontal_offset: usize,
+    scrollbar_state: ScrollbarState,
+    /// Where the log was last drawn, inside its borders.
+    log_area: Rect,
+    /// View position of the line on each row of `log_area`, for clicks.
+    row_positions: Vec<usize>,
+    /// Normal mode key bindings.
+    keymap: Keymap,
+    /// Keys typed so far of a longer binding, e.g. `g` of `gg`.
+    pending_keys: Vec<keymap::Key>,
+    /// Action waiting for its argument, e.g. the name of a mark to set.
+    awaiting_argument: Option<Action>,
+    /// Digits typed before a motion, repeating it, e.g. `10` of `10j`.
+    count_buffer: String,
     should_quit: bool,
+    /// Ask before quitting with `q` while following, filtering or
+    /// searching, where losing the view would be costly.
+    confirm_quit: bool,
+    /// Restore the session saved for files when they're opened.
+    restore_session: bool,
+    /// Waiting for `y` to confirm quitting.
+    pending_quit: bool,
+    /// A file that looks binary, opened once the user answers y.
+    pending_binary: Option<(PathBuf, OpenIn)>,
+    /// Whether the help overlay is open.
+    show_help: bool,
+    /// Lines the help overlay is scrolled down by.
+    help_scroll: usize,
+    /// The `:histogram` overlay, while open.
+    histogram: Option<histogram::Histogram>,
+    /// The `:stats` overlay, while open.
+    stats: Option<stats::Stats>,
+    /// The popup showing the whole current line, while open.
+    detail: Option<detail::Detail>,
+    /// The command palette, while open.
+    palette: Option<palette::Palette>,
     input_mode: InputMode,
-    input_buffer: String,
+    input_buffer: input::Input,
+    /// Commands entered at the `:` prompt.
+    command_history: History,
+    /// The last command run from the `:` prompt, run again by `.`.
+    last_command: Option<String>,
+    /// Tab completion in progress at the `:` prompt.
+    completion: Option<Completion>,
+    /// Queries entered at the `/` prompt, kept apart from commands.
+    search_history: History,
+    /// Shared with the Lua API, see `script::Shared`.
+    status_message: Rc<RefCell<Option<StatusMessage>>>,
+    /// View position where the visual selection started.
+    visual_anchor: usize,
+    /// View position of the moving end of the visual selection.
+    visual_cursor: usize,
+    /// Bookmarked content indices, in ascending order.
+    bookmarks: Vec<usize>,
+    /// Named marks set with `m`, as content indices.
+    marks: HashMap<char, usize>,
+    /// Current line when the search prompt was opened.
+    search_origin: usize,
+    /// Pattern compiled from the query being typed at the search prompt.
+    incremental_regex: Option<Regex>,
+    last_search: Option<String>,
+    search_regex: Option<Regex>,
+    /// Settings changed with `:set`.
+    options: options::Options,
+    timestamps: timestamp::Parser,
+    theme: theme::Theme,
+    style_cache: StyleCache,
+    match_count: MatchCount,
+    /// Keys of structured lines shown as columns, set with `:columns`.
+    columns: Option<columns::Columns>,
+    /// Group continuation lines, such as stack traces, with the line they
+    /// continue into entries, shown collapsed to their first line.
+    folding: bool,
+    /// First lines of the entries expanded with Enter.
+    expanded: HashSet<usize>,
+    /// Extra pattern for continuation lines, from the config.
+    continuation: Option<Regex>,
+    /// Format given with `--format`, or `None` to detect syslog.
+    format: Option<syslog::Format>,
+    /// Whether the current file is syslog, once known.
+    syslog: Option<bool>,
+    /// Profiles from the config's `filetypes`.
+    filetypes: BTreeMap<String, filetype::FileType>,
+    /// Profile given with `--filetype`, or `None` to choose one per file.
+    filetype: Option<String>,
+    /// Whether the current file's profile, if any, has been applied.
+    filetype_applied: bool,
+    /// Lines timestamped before this are dimmed, after `:since!`.
+    dim_before: Option<i64>,
+    /// Active filters; a line is shown only if every filter keeps it.
+    filters: Vec<Filter>,
+    /// Indices into `content` of the lines passing the filters, or `None`
+    /// when every line is shown. `scroll_offset` indexes this view.
+    visible: Rc<RefCell<Option<Vec<usize>>>>,
+    /// Coloring rules registered from Lua.
+    highlights: Rc<RefCell<Vec<script::Highlight>>>,
+    /// Predicate set with `logview.filter`, applied after `filters`.
+    lua_filter: Rc<RefCell<Option<RegistryKey>>>,
+    lua_filter_changed: Rc<Cell<bool>>,
+    /// Commands registered with `logview.command`.
+    commands: Rc<RefCell<HashMap<String, RegistryKey>>>,
+    /// Hook set with `logview.on_line`, run on lines as they're loaded.
+    on_line: Rc<RefCell<Option<RegistryKey>>>,
+    on_line_changed: Rc<Cell<bool>>,
     lua: Lua,
 }
 
 impl App {
-    fn new(file_path: Option<PathBuf>) -> Result<App, Box<dyn Error>> {
-        let content = if let Some(path) = file_path {
-            fs::read_to_string(&path)?
-                .lines()
-                .map(|s| s.to_string())
-                .collect()
-        } else {
-            vec![
-                "Welcome to logview!".to_string(),
-                "Press ':' to open command prompt, 'q' to quit.".to_string(),
-            ]
-        };
+    /// Creates the app, opening each of `files` in a tab, falling back to
+    /// `piped` stdin content and finally to the welcome screen.
+    fn new(
+        files: Vec<PathBuf>,
+        piped: Option<Vec<u8>>,
+        load_options: content::Options,
+        config: &Config,
+    ) -> Result<App, Box<dyn Error>> {
+        // Fail early on a missing or unreadable file; the lines arrive as
+        // they're loaded.
+        let mut buffers = files
+            .into_iter()
+            .map(|path| Buffer::open(path, load_options, config.follow_on_start))
+            .collect::<io::Result<Vec<_>>>()?;
+        if buffers.is_empty() {
+            let content = match piped {
+                Some(bytes) => {
+                    let mut content = Content::decode(&bytes, load_options.encoding);
+                    if let Some(lines) = load_options.tail {
+                        content.keep_last(lines);
+                    }
+                    content
+                }
+                None => Content::from_lines(vec![
+                    "Welcome to logview!".to_string(),
+                    "Press ':' to open command prompt, 'q' to quit.".to_string(),
+                ]),
+            };
+            buffers.push(Buffer::new(content, None, None, false));
+        }
 
-        let lua = Lua::new();
+        let content = Rc::new(RefCell::new(Content::from_lines(Vec::new())));
+        let scroll_offset = Rc::new(Cell::new(0));
+        let cursor_line = Rc::new(Cell::new(0));
+        let visible = Rc::new(RefCell::new(None));
+        let highlights = Rc::new(RefCell::new(Vec::new()));
+        let status_message = Rc::new(RefCell::new(None));
+        let lua_filter = Rc::new(RefCell::new(None));
+        let lua_filter_changed = Rc::new(Cell::new(false));
+        let commands = Rc::new(RefCell::new(HashMap::new()));
+        let on_line = Rc::new(RefCell::new(None));
+        let on_line_changed = Rc::new(Cell::new(false));
+        let lua = script::new_lua(config.safe)?;
+        script::install(
+            &lua,
+            &script::Shared {
+                content: content.clone(),
+                scroll_offset: scroll_offset.clone(),
+                cursor_line: cursor_line.clone(),
+                visible: visible.clone(),
+                status_message: status_message.clone(),
+                highlights: highlights.clone(),
+                lua_filter: lua_filter.clone(),
+                lua_filter_changed: lua_filter_changed.clone(),
+                commands: commands.clone(),
+                on_line: on_line.clone(),
+                on_line_changed: on_line_changed.clone(),
+            },
+        )?;
+        let mut lua_dirs: Vec<PathBuf> = config
+            .lua_path
+            .iter()
+            .map(|dir| expand_path(&dir.to_string_lossy()))
+            .collect();
+        lua_dirs.extend(dirs::home_dir().map(|home| home.join(DEFAULT_LUA_DIR)));
+        // Each is put first, so the first configured is searched first.
+        for dir in lua_dirs.iter().rev() {
+            script::add_to_path(&lua, dir)?;
+        }
 
-        Ok(App {
+        let mut app = App {
             content,
+            watcher: None,
+            loading: None,
+            load_options,
+            following: false,
+            new_lines: None,
+            file_path: None,
+            buffers: Vec::new(),
+            active: 0,
+            split: None,
+            scroll_offset,
+            cursor_line,
+            viewport_height: 0,
+            log_area: Rect::default(),
+            row_positions: Vec::new(),
+            viewport_width: 0,
+            horizontal_offset: 0,
+            scrollbar_state: ScrollbarState::default(),
+            keymap: Keymap::default(),
+            pending_keys: Vec::new(),
+            awaiting_argument: None,
+            count_buffer: String::new(),
             should_quit: false,
+            confirm_quit: true,
+            restore_session: true,
+            pending_quit: false,
+            pending_binary: None,
+            show_help: false,
+            stats: None,
+            histogram: None,
+            detail: None,
+            palette: None,
+            help_scroll: 0,
             input_mode: InputMode::Normal,
-            input_buffer: String::new(),
+            input_buffer: input::Input::default(),
+            command_history: History::default(),
+            last_command: None,
+            completion: None,
+            search_history: History::default(),
+            status_message,
+            visual_anchor: 0,
+            visual_cursor: 0,
+            bookmarks: Vec::new(),
+            marks: HashMap::new(),
+            search_origin: 0,
+            incremental_regex: None,
+            last_search: None,
+            search_regex: None,
+            options: options::Options {
+                ignore_case: false,
+                wrapscan: config.wrapscan.unwrap_or(true),
+                show_line_numbers: config.show_line_numbers,
+                relative_line_numbers: false,
+                level_coloring: config.level_coloring,
+                tab_width: config.tab_width.unwrap_or(DEFAULT_TAB_WIDTH).max(1),
+                reltime: false,
+                json: false,
+                wrap: config.wrap,
+                mouse: true,
+                scrollbind: false,
+                list: false,
+            },
+            timestamps: timestamp::Parser::new(None).expect("default formats are valid"),
+            theme: theme::Theme::default(),
+            style_cache: StyleCache::default(),
+            match_count: MatchCount::default(),
+            columns: None,
+            folding: false,
+            expanded: HashSet::new(),
+            continuation: None,
+            format: None,
+            syslog: None,
+            filetypes: config.filetypes.clone(),
+            filetype: None,
+            filetype_applied: false,
+            dim_before: None,
+            filters: Vec::new(),
+            visible,
+            highlights,
+            lua_filter,
+            lua_filter_changed,
+            commands,
+            on_line,
+            on_line_changed,
             lua,
-        })
+        };
+        app.swap_with(&mut buffers[0]);
+        app.buffers = buffers;
+        if let Some(note) = utf8_note(&app.content.borrow()) {
+            app.status_message.replace(Some(StatusMessage::info(note)));
+        }
+        Ok(app)
+    }
+
+    /// Swaps the state of the active tab with `buffer`'s.
+    fn swap_with(&mut self, buffer: &mut Buffer) {
+        mem::swap(&mut *self.content.borrow_mut(), &mut buffer.content);
+        mem::swap(&mut self.file_path, &mut buffer.file_path);
+        mem::swap(&mut self.watcher, &mut buffer.watcher);
+        mem::swap(&mut self.loading, &mut buffer.loading);
+        mem::swap(&mut self.following, &mut buffer.following);
+        mem::swap(&mut self.new_lines, &mut buffer.new_lines);
+        buffer.scroll_offset = self.scroll_offset.replace(buffer.scroll_offset);
+        buffer.cursor_line = self.cursor_line.replace(buffer.cursor_line);
+        mem::swap(&mut self.horizontal_offset, &mut buffer.horizontal_offset);
+        mem::swap(&mut *self.visible.borrow_mut(), &mut buffer.visible);
+        mem::swap(&mut self.filters, &mut buffer.filters);
+        mem::swap(&mut self.folding, &mut buffer.folding);
+        mem::swap(&mut self.expanded, &mut buffer.expanded);
+        mem::swap(&mut self.bookmarks, &mut buffer.bookmarks);
+        mem::swap(&mut self.marks, &mut buffer.marks);
+        mem::swap(&mut self.last_search, &mut buffer.last_search);
+        mem::swap(&mut self.search_regex, &mut buffer.search_regex);
+        mem::swap(&mut self.style_cache, &mut buffer.style_cache);
+        mem::swap(&mut self.match_count, &mut buffer.match_count);
+        mem::swap(&mut self.syslog, &mut buffer.syslog);
+        mem::swap(&mut self.filetype_applied, &mut buffer.filetype_applied);
+        mem::swap(&mut self.dim_before, &mut buffer.dim_before);
+    }
+
+    /// Makes tab `index` the active one.
+    fn switch_tab(&mut self, index: usize) {
+        if index == self.active || index >= self.buffers.len() {
+            return;
+        }
+        self.swap_tab(index);
+        // The Lua filter applies to every tab and may have changed since.
+        if self.lua_filter.borrow().is_some() {
+            self.refilter();
+        }
+    }
+
+    /// Makes tab `index` the active one, as is.
+    fn swap_tab(&mut self, index: usize) {
+        if index == self.active {
+            return;
+        }
+        let mut buffers = mem::take(&mut self.buffers);
+        self.swap_with(&mut buffers[self.active]);
+        self.swap_with(&mut buffers[index]);
+        self.buffers = buffers;
+        self.active = index;
+    }
+
+    /// Handles `:split` and `:vsplit`, showing the current tab in a second
+    /// pane, or `file` opened in a new tab.
+    fn split(&mut self, direction: Direction, file: &str) {
+        let other = Pane {
+            buffer: self.active,
+            scroll_offset: self.scroll_offset.get(),
+            cursor_line: self.cursor_line.get(),
+            horizontal_offset: self.horizontal_offset,
+        };
+        self.split = Some(Split {
+            direction,
+            other,
+            focused: 1,
+        });
+        if !file.is_empty() {
+            self.new_tab(file);
+        }
+    }
+
+    /// Handles `:only`, closing the pane that isn't focused.
+    fn close_split(&mut self) {
+        if self.split.take().is_none() {
+            self.show_error("only: there's no split");
+        }
+    }
+
+    /// Swaps the state of the focused pane with the other one's, making
+    /// it the focused one.
+    fn swap_panes(&mut self) {
+        let Some(split) = &mut self.split else {
+            return;
+        };
+        let other = mem::replace(
+            &mut split.other,
+            Pane {
+                buffer: self.active,
+                scroll_offset: self.scroll_offset.get(),
+                cursor_line: self.cursor_line.get(),
+                horizontal_offset: self.horizontal_offset,
+            },
+        );
+        split.focused = 1 - split.focused;
+        self.swap_tab(other.buffer);
+        self.scroll_offset.set(other.scroll_offset);
+        self.cursor_line.set(other.cursor_line);
+        self.horizontal_offset = other.horizontal_offset;
+    }
+
+    /// Handles `ctrl-w`, moving the focus to the other pane of a split.
+    fn focus_other_pane(&mut self) {
+        let Some(split) = &self.split else {
+            return;
+        };
+        let refilter = split.other.buffer != self.active && self.lua_filter.borrow().is_some();
+        self.swap_panes();
+        if refilter {
+            self.refilter();
+        }
+    }
+
+    /// Handles `gt`, going to tab `count` if given, and `gT`, going back
+    /// `count` tabs.
+    fn next_tab(&mut self, forward: bool, count: Option<usize>) {
+        let len = self.buffers.len();
+        let index = match (forward, count) {
+            (true, Some(count)) => count.clamp(1, len) - 1,
+            (true, None) => (self.active + 1) % len,
+            (false, count) => (self.active + len - count.unwrap_or(1) % len) % len,
+        };
+        self.switch_tab(index);
+    }
+
+    /// Handles `:tabnew <file>`, opening it in a new tab after this one.
+    fn new_tab(&mut self, arg: &str) {
+        if arg.is_empty() {
+            self.show_error("tabnew: missing file name");
+            return;
+        }
+        let path = expand_path(arg);
+        if !self.confirm_binary(&path, OpenIn::NewTab) {
+            self.open_tab(path);
+        }
+    }
+
+    /// Opens `path` in a new tab after this one.
+    fn open_tab(&mut self, path: PathBuf) {
+        match Buffer::open(path.clone(), self.load_options, false) {
+            Ok(buffer) => {
+                self.buffers.insert(self.active + 1, buffer);
+                if let Some(split) = &mut self.split
+                    && split.other.buffer > self.active
+                {
+                    split.other.buffer += 1;
+                }
+                self.switch_tab(self.active + 1);
+            }
+            Err(err) => self.show_error(format!("tabnew: {}: {err}", path.display())),
+        }
+    }
+
+    /// Handles `:tabclose`, closing the active tab unless it's the last.
+    fn close_tab(&mut self) {
+        if self.buffers.len() == 1 {
+            self.show_error("tabclose: can't close the last tab");
+            return;
+        }
+        if let Err(err) = self.save_session() {
+            self.show_error(format!("session: {err}"));
+        }
+        let closed = self.active;
+        self.switch_tab(if closed == 0 { 1 } else { closed - 1 });
+        self.buffers.remove(closed);
+        if self.active > closed {
+            self.active -= 1;
+        }
+        if let Some(split) = &mut self.split {
+            if split.other.buffer == closed {
+                split.other.buffer = self.active;
+            } else if split.other.buffer > closed {
+                split.other.buffer -= 1;
+            }
+        }
+    }
+
+    /// Name shown for tab `index`.
+    fn tab_title(&self, index: usize) -> String {
+        let path = if index == self.active {
+            &self.file_path
+        } else {
+            &self.buffers[index].file_path
+        };
+        match path {
+            Some(path) => path
+                .file_name()
+                .unwrap_or(path.as_os_str())
+                .to_string_lossy()
+                .into_owned(),
+            None => "[stdin]".to_string(),
+        }
+    }
+
+    fn handle_mouse_event(&mut self, mouse: MouseEvent) {
+        let before = self.view_origin();
+        self.handle_mouse(mouse);
+        self.scroll_bound_pane(before);
+    }
+
+    fn handle_mouse(&mut self, mouse: MouseEvent) {
+        match mouse.kind {
+            MouseEventKind::ScrollDown if self.show_help => {
+                self.help_scroll = self.help_scroll.saturating_add(MOUSE_SCROLL_LINES);
+            }
+            MouseEventKind::ScrollUp if self.show_help => {
+                self.help_scroll = self.help_scroll.saturating_sub(MOUSE_SCROLL_LINES);
+            }
+            MouseEventKind::ScrollDown => {
+                self.scroll_down(MOUSE_SCROLL_LINES);
+                self.keep_cursor_on_screen();
+            }
+            MouseEventKind::ScrollUp => {
+                self.scroll_up(MOUSE_SCROLL_LINES);
+                self.keep_cursor_on_screen();
+            }
+            MouseEventKind::Down(MouseButton::Left) if !self.show_help => {
+                self.click(mouse.column, mouse.row);
+            }
+            _ => {}
+        }
     }
 
-    fn handle_key_event(&mut self, key: KeyCode) {
+    /// Makes the line clicked at `column`, `row` the current line, or the
+    /// end of the selection in visual mode.
+    fn click(&mut self, column: u16, row: u16) {
+        if !self.log_area.contains(Position::new(column, row)) {
+            return;
+        }
+        let Some(&position) = self.row_positions.get(usize::from(row - self.log_area.y)) else {
+            return;
+        };
         match self.input_mode {
-            InputMode::Normal => match key {
-                KeyCode::Char('q') => self.should_quit = true,
-                KeyCode::Char(':') => {
-                    self.input_mode = InputMode::Command;
-                    self.input_buffer.clear();
+            InputMode::Normal => self.move_cursor(position),
+            InputMode::Visual => {
+                let delta = position as isize - self.visual_cursor as isize;
+                self.move_visual_cursor(delta);
+            }
+            InputMode::Command | InputMode::Search => {}
+        }
+    }
+
+    fn handle_key_event(&mut self, key: KeyEvent) {
+        let before = self.view_origin();
+        self.handle_key(key);
+        self.scroll_bound_pane(before);
+    }
+
+    /// The tab shown in the focused pane and its top line and horizontal
+    /// offset, for `:set scrollbind`.
+    fn view_origin(&self) -> (usize, usize, usize) {
+        (
+            self.active,
+            self.scroll_offset.get(),
+            self.horizontal_offset,
+        )
+    }
+
+    /// With `:set scrollbind`, scrolls the other pane of a split as far as
+    /// the focused one moved from `before`, unless it changed tabs.
+    fn scroll_bound_pane(&mut self, before: (usize, usize, usize)) {
+        let (active, top, horizontal_offset) = self.view_origin();
+        if !self.options.scrollbind || active != before.0 {
+            return;
+        }
+        let Some(split) = &mut self.split else {
+            return;
+        };
+        let delta = top as isize - before.1 as isize;
+        let horizontal_delta = horizontal_offset as isize - before.2 as isize;
+        if delta == 0 && horizontal_delta == 0 {
+            return;
+        }
+        let other = &mut split.other;
+        other.scroll_offset = other.scroll_offset.saturating_add_signed(delta);
+        other.cursor_line = other.cursor_line.saturating_add_signed(delta);
+        other.horizontal_offset = other
+            .horizontal_offset
+            .saturating_add_signed(horizontal_delta);
+        self.swap_panes();
+        self.clamp_scroll();
+        self.horizontal_offset = self.horizontal_offset.min(self.max_horizontal_offset());
+        self.swap_panes();
+    }
+
+    /// Inserts pasted `text` at the cursor of the command or search prompt.
+    /// Pastes elsewhere are ignored rather than run as keys.
+    fn handle_paste(&mut self, text: &str) {
+        if let Some(palette) = &mut self.palette {
+            palette.input.insert(text);
+            palette.filter();
+            return;
+        }
+        match self.input_mode {
+            InputMode::Command => {
+                self.completion = None;
+                self.input_buffer.insert(text);
+            }
+            InputMode::Search => {
+                self.input_buffer.insert(text);
+                self.update_incremental_search();
+            }
+            InputMode::Normal | InputMode::Visual => {}
+        }
+    }
+
+    /// Runs `command` as if entered at the `:` prompt, remembering it in the
+    /// history and for `.`.
+    fn submit_command(&mut self, command: &str) {
+        self.command_history.push(command);
+        if !command.trim().is_empty() {
+            self.last_command = Some(command.to_string());
+        }
+        self.execute_command(command);
+    }
+
+    /// Opens the command palette with the built-in and Lua commands and the
+    /// files viewed recently.
+    fn open_palette(&mut self) {
+        let mut items: Vec<palette::Item> = help::COMMANDS
+            .iter()
+            .map(|(name, args, description)| palette::Item::command(name, args, description))
+            .collect();
+        let mut user_commands: Vec<String> = self.commands.borrow().keys().cloned().collect();
+        user_commands.sort();
+        items.extend(
+            user_commands
+                .iter()
+                .map(|name| palette::Item::command(name, "", "defined in Lua")),
+        );
+        items.extend(
+            session::recent_files()
+                .iter()
+                .map(|path| palette::Item::file(&path.to_string_lossy())),
+        );
+        self.palette = Some(palette::Palette::new(items));
+    }
+
+    fn handle_palette_key(&mut self, key: KeyEvent) {
+        let Some(palette) = &mut self.palette else {
+            return;
+        };
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Esc => self.palette = None,
+            KeyCode::Down | KeyCode::Tab => palette.select_next(true),
+            KeyCode::Up | KeyCode::BackTab => palette.select_next(false),
+            KeyCode::Char('n') if ctrl => palette.select_next(true),
+            KeyCode::Char('p') if ctrl => palette.select_next(false),
+            KeyCode::Enter => {
+                let selected = palette
+                    .selected()
+                    .map(|item| (item.command.clone(), item.needs_argument));
+                self.palette = None;
+                match selected {
+                    // Left at the prompt to type the arguments.
+                    Some((command, true)) => {
+                        self.input_mode = InputMode::Command;
+                        self.input_buffer.set(format!("{command} "));
+                        self.command_history.reset();
+                    }
+                    Some((command, false)) => self.submit_command(&command),
+                    None => {}
+                }
+            }
+            _ => {
+                if palette.input.edit(key) {
+                    palette.filter();
+                }
+            }
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent) {
+        self.status_message.replace(None);
+        if key.code != KeyCode::Tab {
+            self.completion = None;
+        }
+        if self.pending_quit {
+            self.pending_quit = false;
+            if key.code == KeyCode::Char('y') {
+                self.should_quit = true;
+            }
+            return;
+        }
+        if let Some((path, open_in)) = self.pending_binary.take() {
+            if key.code == KeyCode::Char('y') {
+                match open_in {
+                    OpenIn::Current => self.start_loading(path, LoadKind::Open),
+                    OpenIn::NewTab => self.open_tab(path),
+                }
+            }
+            return;
+        }
+        if self.show_help {
+            match key.code {
+                KeyCode::Char('j') | KeyCode::Down => self.help_scroll += 1,
+                KeyCode::Char('k') | KeyCode::Up => {
+                    self.help_scroll = self.help_scroll.saturating_sub(1)
+                }
+                _ => self.show_help = false,
+            }
+            return;
+        }
+        if self.stats.take().is_some() || self.histogram.take().is_some() {
+            return;
+        }
+        if let Some(detail) = &mut self.detail {
+            match key.code {
+                KeyCode::Char('j') | KeyCode::Down => detail.scroll += 1,
+                KeyCode::Char('k') | KeyCode::Up => detail.scroll = detail.scroll.saturating_sub(1),
+                _ => self.detail = None,
+            }
+            return;
+        }
+        if self.palette.is_some() {
+            self.handle_palette_key(key);
+            return;
+        }
+        match self.input_mode {
+            InputMode::Normal if let Some(action) = self.awaiting_argument.take() => {
+                if let KeyCode::Char(name) = key.code {
+                    match action {
+                        Action::SetMark if name.is_ascii_alphabetic() => self.set_mark(name),
+                        Action::JumpToMark => self.jump_to_mark(name),
+                        _ => {}
+                    }
                 }
+            }
+            // `0` only continues a count, leaving it free as a motion.
+            InputMode::Normal
+                if let KeyCode::Char(digit @ '0'..='9') = key.code
+                    && key.modifiers.is_empty()
+                    && self.pending_keys.is_empty()
+                    && (digit != '0' || !self.count_buffer.is_empty()) =>
+            {
+                self.count_buffer.push(digit);
+            }
+            InputMode::Normal => {
+                self.pending_keys.push(key.into());
+                match self.keymap.lookup(&self.pending_keys) {
+                    Lookup::Action(action) => {
+                        self.pending_keys.clear();
+                        self.perform(action);
+                    }
+                    // The count carries over to the rest of the keys.
+                    Lookup::Prefix => {}
+                    Lookup::Unbound => {
+                        self.pending_keys.clear();
+                        self.count_buffer.clear();
+                    }
+                }
+            }
+            InputMode::Visual => match key.code {
+                KeyCode::Char('j') | KeyCode::Down => self.move_visual_cursor(1),
+                KeyCode::Char('k') | KeyCode::Up => self.move_visual_cursor(-1),
+                KeyCode::Char('y') => {
+                    let (first, last) = self.visual_selection();
+                    self.input_mode = InputMode::Normal;
+                    self.copy_lines(first, last);
+                }
+                KeyCode::Esc | KeyCode::Char('V') => self.input_mode = InputMode::Normal,
                 _ => {}
             },
-            InputMode::Command => match key {
+            InputMode::Command if key.code == KeyCode::Tab => self.complete_command(),
+            InputMode::Command => match key.code {
                 KeyCode::Enter => {
-                    let command = self.input_buffer.clone();
-                    if command == "quit()" {
-                        self.should_quit = true;
-                    } else {
-                        let _ = self.lua.load(&command).exec();
+                    let command = self.input_buffer.as_str().to_string();
+                    self.input_mode = InputMode::Normal;
+                    self.input_buffer.clear();
+                    self.submit_command(&command);
+                }
+                KeyCode::Up => {
+                    if let Some(command) = self.command_history.previous(self.input_buffer.as_str())
+                    {
+                        self.input_buffer.set(command);
+                    }
+                }
+                KeyCode::Down => {
+                    if let Some(command) = self.command_history.next() {
+                        self.input_buffer.set(command);
+                    }
+                }
+                KeyCode::Esc => {
+                    self.input_mode = InputMode::Normal;
+                    self.input_buffer.clear();
+                }
+                _ => {
+                    self.input_buffer.edit(key);
+                }
+            },
+            InputMode::Search => match key.code {
+                KeyCode::Enter => {
+                    if !self.input_buffer.is_empty() {
+                        self.search_history.push(self.input_buffer.as_str());
+                        match self.compile_search(self.input_buffer.as_str()) {
+                            Ok(regex) => {
+                                self.last_search = Some(self.input_buffer.as_str().to_string());
+                                self.search_regex = Some(regex);
+                            }
+                            Err(err) => self.show_error(err.to_string()),
+                        }
                     }
+                    self.incremental_regex = None;
                     self.input_mode = InputMode::Normal;
                     self.input_buffer.clear();
                 }
                 KeyCode::Esc => {
+                    self.move_cursor(self.search_origin);
+                    self.incremental_regex = None;
                     self.input_mode = InputMode::Normal;
                     self.input_buffer.clear();
                 }
-                KeyCode::Backspace => {
-                    self.input_buffer.pop();
+                KeyCode::Up => {
+                    if let Some(query) = self.search_history.previous(self.input_buffer.as_str()) {
+                        self.input_buffer.set(query);
+                        self.update_incremental_search();
+                    }
+                }
+                KeyCode::Down => {
+                    if let Some(query) = self.search_history.next() {
+                        self.input_buffer.set(query);
+                        self.update_incremental_search();
+                    }
                 }
-                KeyCode::Char(c) => {
-                    self.input_buffer.push(c);
+                _ => {
+                    if self.input_buffer.edit(key) {
+                        self.update_incremental_search();
+                    }
                 }
-                _ => {}
             },
         }
     }
-}
 
-fn main() -> Result<(), Box<dyn Error>> {
-    let args = Args::parse();
-    let _config = Config::load()?;
+    /// Jumps to the first line at or below the search origin matching the
+    /// query typed so far, or back to the origin if nothing matches.
+    fn update_incremental_search(&mut self) {
+        // Partially typed patterns are often invalid; treat those as no match.
+        self.incremental_regex = if self.input_buffer.is_empty() {
+            None
+        } else {
+            self.compile_search(self.input_buffer.as_str()).ok()
+        };
+        let found = self
+            .incremental_regex
+            .as_ref()
+            .and_then(|regex| self.find_forward(self.search_origin, |line| regex.is_match(line)));
 
-    enable_raw_mode()?;
-    let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
-    let backend = CrosstermBackend::new(stdout);
-    let mut terminal = Terminal::new(backend)?;
+        match found {
+            Some(position) => self.jump_to_position(position),
+            None => self.move_cursor(self.search_origin),
+        }
+    }
 
-    let mut app = App::new(args.file)?;
+    fn show_message(&mut self, text: impl Into<String>) {
+        self.status_message.replace(Some(StatusMessage::info(text)));
+    }
 
-    let res = run_app(&mut terminal, &mut app);
+    fn show_error(&mut self, text: impl Into<String>) {
+        self.status_message
+            .replace(Some(StatusMessage::error(text)));
+    }
 
-    disable_raw_mode()?;
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )?;
-    terminal.show_cursor()?;
+    fn compile_search(&self, query: &str) -> Result<Regex, regex::Error> {
+        RegexBuilder::new(query)
+            .case_insensitive(self.options.ignore_case)
+            .build()
+    }
 
-    if let Err(err) = res {
-        println!("{err:?}");
+    /// Recompiles the last search so n/N and highlighting pick up a new
+    /// `ignorecase`.
+    fn recompile_search(&mut self) {
+        if let Some(query) = &self.last_search {
+            self.search_regex = self.compile_search(query).ok();
+        }
     }
 
-    Ok(())
-}
+    /// Query to highlight: the one being typed, else the last confirmed one.
+    fn active_search(&self) -> Option<&Regex> {
+        if self.input_mode == InputMode::Search && !self.input_buffer.is_empty() {
+            self.incremental_regex.as_ref()
+        } else {
+            self.search_regex.as_ref()
+        }
+    }
 
-fn run_app<B: ratatui::backend::Backend>(
-    terminal: &mut Terminal<B>,
-    app: &mut App,
-) -> io::Result<()> {
-    loop {
-        terminal.draw(|f| ui(f, app))?;
+    /// Moves to the next (or previous) line matching the last confirmed
+    /// search.
+    fn search_next(&mut self, forward: bool) {
+        let Some(regex) = self.search_regex.clone() else {
+            self.show_message("no previous search");
+            return;
+        };
+        let not_found = format!("pattern not found: {}", regex.as_str());
+        self.jump_to_next_match(forward, &not_found, |line| regex.is_match(line));
+    }
 
-        if let Event::Key(key) = event::read()? {
-            if key.kind == KeyEventKind::Press {
-                app.handle_key_event(key.code);
+    /// Handles `*` and `#`, searching forward or backward for the word
+    /// `word_query` picks.
+    fn search_word(&mut self, forward: bool) {
+        let Some(query) = self.word_query() else {
+            return;
+        };
+        self.search_history.push(&query);
+        match self.compile_search(&query) {
+            Ok(regex) => {
+                self.last_search = Some(query);
+                self.search_regex = Some(regex);
+                self.search_next(forward);
             }
+            Err(err) => self.show_error(err.to_string()),
+        }
+    }
+
+    /// A pattern matching the whole word of the current line at the left
+    /// edge of the view, or else the first one after it, as shown. Words
+    /// are letters, digits, `_` and inner `-`, so IDs like `req-42` are
+    /// taken whole. Shows an error if there's none.
+    fn word_query(&mut self) -> Option<String> {
+        let word = (self.visible_len() > 0)
+            .then(|| {
+                let content = self.content.borrow();
+                let line = self.display(content.line(self.line_at(self.current_position())));
+                let (text, _) = ansi::parse(&line);
+                // Skip to the character at the left edge, expanding tabs.
+                let mut column = 0;
+                let start = text
+                    .char_indices()
+                    .find(|&(_, c)| {
+                        column = match c {
+                            '\t' => next_tab_stop(column, self.options.tab_width),
+                            _ => column + 1,
+                        };
+                        column > self.horizontal_offset
+                    })
+                    .map_or(text.len(), |(start, _)| start);
+                let is_word = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+                // Back to the start of a word cut by the edge.
+                let start = text[..start]
+                    .char_indices()
+                    .rev()
+                    .take_while(|&(_, c)| is_word(c))
+                    .last()
+                    .map_or(start, |(start, _)| start);
+                text[start..]
+                    .split(|c: char| !is_word(c))
+                    .map(|word| word.trim_matches('-'))
+                    .find(|word| !word.is_empty())
+                    .map(str::to_string)
+            })
+            .flatten();
+        match word {
+            Some(word) => Some(format!("\\b{}\\b", regex::escape(&word))),
+            None => {
+                self.show_error("no word on the current line");
+                None
+            }
+        }
+    }
+
+    /// Moves to the next (or previous) line with a level of at least
+    /// `level`, or exactly `level` if `exact`, for `]e` and `]w`.
+    fn jump_to_level(&mut self, forward: bool, level: Level, exact: bool) {
+        let not_found = format!("no {} lines", level.name());
+        let syslog = self.is_syslog();
+        self.jump_to_next_match(forward, &not_found, |line| {
+            line_level(line, syslog).is_some_and(|found| {
+                if exact {
+                    found == level
+                } else {
+                    found >= level
+                }
+            })
+        });
+    }
+
+    /// Moves to the next (or previous) line for which `is_match` holds,
+    /// wrapping around the ends of the file with `wrapscan`. `not_found` is
+    /// shown when no line matches.
+    fn jump_to_next_match(
+        &mut self,
+        forward: bool,
+        not_found: &str,
+        is_match: impl Fn(&str) -> bool,
+    ) {
+        let current = self.current_position();
+        let (found, wrapped) = if forward {
+            match self.find_forward(current + 1, &is_match) {
+                Some(line) => (Some(line), false),
+                None if self.options.wrapscan => (self.find_forward(0, &is_match), true),
+                None => {
+                    self.show_error("search hit BOTTOM without match");
+                    return;
+                }
+            }
+        } else {
+            match self.find_backward(current, &is_match) {
+                Some(line) => (Some(line), false),
+                None if self.options.wrapscan => {
+                    (self.find_backward(self.visible_len(), &is_match), true)
+                }
+                None => {
+                    self.show_error("search hit TOP without match");
+                    return;
+                }
+            }
+        };
+
+        match found {
+            Some(position) => {
+                if wrapped {
+                    self.show_message("search wrapped");
+                }
+                self.jump_to_position(position);
+            }
+            None => self.show_error(not_found),
+        }
+    }
+
+    /// Returns the first view position at or after `from` whose line
+    /// matches.
+    fn find_forward(&self, from: usize, is_match: impl Fn(&str) -> bool) -> Option<usize> {
+        (from..self.visible_len()).find(|&position| self.matches_at(position, &is_match))
+    }
+
+    /// Returns the last view position before `before` whose line matches.
+    fn find_backward(&self, before: usize, is_match: impl Fn(&str) -> bool) -> Option<usize> {
+        (0..before.min(self.visible_len()))
+            .rev()
+            .find(|&position| self.matches_at(position, &is_match))
+    }
+
+    /// Whether the line at view `position`, or any line of the collapsed
+    /// entry there, matches.
+    fn matches_at(&self, position: usize, is_match: impl Fn(&str) -> bool) -> bool {
+        let index = self.line_at(position);
+        let lines = match self.collapsed_entry(index) {
+            Some(end) => index..end,
+            None => index..index + 1,
+        };
+        let content = self.content.borrow();
+        lines
+            .into_iter()
+            .any(|index| is_match(&content.line(index)))
+    }
+
+    /// Whether `line` continues the entry of the line before it.
+    fn is_continuation(&self, line: &str) -> bool {
+        line.starts_with(char::is_whitespace)
+            || line.starts_with("at ")
+            || self
+                .continuation
+                .as_ref()
+                .is_some_and(|regex| regex.is_match(line))
+    }
+
+    /// Index of the first line of the entry containing line `index`.
+    fn entry_start(&self, index: usize) -> usize {
+        let content = self.content.borrow();
+        (1..=index)
+            .rev()
+            .find(|&i| !self.is_continuation(&content.line(i)))
+            .unwrap_or(0)
+    }
+
+    /// Index just past the last line of the entry starting at `start`.
+    fn entry_end(&self, start: usize) -> usize {
+        let content = self.content.borrow();
+        (start + 1..content.len())
+            .find(|&i| !self.is_continuation(&content.line(i)))
+            .unwrap_or(content.len())
+    }
+
+    /// If line `index` is shown for a collapsed entry of several lines,
+    /// returns the end of the entry.
+    fn collapsed_entry(&self, index: usize) -> Option<usize> {
+        if !self.folding || self.expanded.contains(&index) {
+            return None;
+        }
+        if index > 0 && self.is_continuation(&self.content.borrow().line(index)) {
+            return None;
+        }
+        Some(self.entry_end(index)).filter(|&end| end > index + 1)
+    }
+
+    fn set_folding(&mut self, enabled: bool) {
+        let top = (self.visible_len() > 0).then(|| self.line_at(self.current_position()));
+        self.folding = enabled;
+        self.expanded.clear();
+        self.refilter();
+        if let Some(top) = top {
+            let top = if enabled { self.entry_start(top) } else { top };
+            self.jump_to_line(top);
+        }
+    }
+
+    /// Expands or collapses the entry at the current line. Returns whether
+    /// there was one, spanning several lines with `:set fold`.
+    fn toggle_entry(&mut self) -> bool {
+        if !self.folding || self.visible_len() == 0 {
+            return false;
+        }
+        let start = self.entry_start(self.line_at(self.current_position()));
+        let end = self.entry_end(start);
+        if end == start + 1 {
+            return false;
+        }
+
+        // Entries are kept or filtered out whole, so the rest of the entry
+        // can be spliced in or out after its first line.
+        let position = self.position_of(start);
+        let expanded = !self.expanded.remove(&start);
+        if expanded {
+            self.expanded.insert(start);
+        }
+        if let Some(visible) = self.visible.borrow_mut().as_mut() {
+            if expanded {
+                visible.splice(position + 1..position + 1, start + 1..end);
+            } else {
+                visible.drain(position + 1..position + (end - start));
+            }
+        }
+        self.jump_to_position(position);
+        true
+    }
+
+    /// Opens the popup showing the whole current line, as on Enter outside
+    /// a folded entry.
+    fn show_detail(&mut self) {
+        if self.visible_len() == 0 {
+            return;
+        }
+        let index = self.line_at(self.current_position());
+        let content = self.content.borrow();
+        let line = content.line(index);
+        let (text, _) = ansi::parse(&line);
+        let detail =
+            detail::Detail::new(index + 1, &text, self.is_syslog(), self.options.tab_width);
+        drop(content);
+        self.detail = Some(detail);
+    }
+
+    /// Number of lines in the (possibly filtered) view.
+    fn visible_len(&self) -> usize {
+        self.visible
+            .borrow()
+            .as_ref()
+            .map_or_else(|| self.content.borrow().len(), Vec::len)
+    }
+
+    /// Index into `content` of the line at view `position`.
+    fn line_at(&self, position: usize) -> usize {
+        self.visible
+            .borrow()
+            .as_ref()
+            .map_or(position, |visible| visible[position])
+    }
+
+    /// View position of `line`, or of the next visible line if it is
+    /// filtered out.
+    fn position_of(&self, line: usize) -> usize {
+        script::position_of(self.visible.borrow().as_ref(), line)
+    }
+
+    /// Handles `:filter [pattern]` and `:filter! [pattern]`, stacking a new
+    /// filter on the active ones. An empty pattern clears all filters and
+    /// `pop` removes the most recent one.
+    fn push_filter(&mut self, pattern: &str, invert: bool) {
+        if pattern.is_empty() {
+            self.clear_filter();
+            return;
+        }
+        if pattern == "pop" && !invert {
+            self.pop_filter();
+            return;
+        }
+
+        match self.compile_search(pattern) {
+            Ok(regex) => {
+                self.filters.push(Filter::Pattern { regex, invert });
+                self.refilter();
+                if self.visible_len() == 0 {
+                    self.show_message(format!("filter: no lines match '{pattern}'"));
+                }
+            }
+            Err(err) => self.show_error(err.to_string()),
+        }
+    }
+
+    /// Handles `:since <time>`, stacking a filter hiding the lines
+    /// timestamped before `time`.
+    fn filter_since(&mut self, time: &str) {
+        if time.is_empty() {
+            self.show_error("since: needs a time, e.g. 10m or 2024-01-01T00:00");
+            return;
+        }
+        match self.timestamps.parse_cutoff(time) {
+            Ok(cutoff) => {
+                self.filters.push(Filter::Since {
+                    cutoff,
+                    text: time.to_string(),
+                });
+                self.refilter();
+                if self.visible_len() == 0 {
+                    self.show_message(format!("since: no lines since {time}"));
+                }
+            }
+            Err(err) => self.show_error(format!("since: {err}")),
+        }
+    }
+
+    /// Handles `:since! [time]`, dimming the lines timestamped before
+    /// `time`, or no longer dimming any without one.
+    fn dim_before(&mut self, time: &str) {
+        if time.is_empty() {
+            self.dim_before = None;
+            return;
+        }
+        match self.timestamps.parse_cutoff(time) {
+            Ok(cutoff) => self.dim_before = Some(cutoff),
+            Err(err) => self.show_error(format!("since!: {err}")),
+        }
+    }
+
+    fn pop_filter(&mut self) {
+        if self.filters.pop().is_none() {
+            self.show_message("no active filters");
+            return;
+        }
+        self.refilter();
+    }
+
+    /// Drops every filter, including one set with `logview.filter`.
+    fn clear_filter(&mut self) {
+        self.filters.clear();
+        self.lua_filter.replace(None);
+        self.refilter();
+    }
+
+    /// Recomputes the visible lines from scratch after the filters changed.
+    /// The lines at the top of the viewport and under the cursor stay
+    /// there, or the next ones shown if they're now hidden.
+    fn refilter(&mut self) {
+        let view = (self.visible_len() > 0).then(|| {
+            (
+                self.line_at(self.scroll_offset.get().min(self.max_scroll_offset())),
+                self.line_at(self.current_position()),
+            )
+        });
+        if self.filters.is_empty() && self.lua_filter.borrow().is_none() && !self.folding {
+            self.visible.replace(None);
+        } else {
+            self.visible.replace(Some(Vec::new()));
+            self.extend_visible(0);
+        }
+        if let Some((top, cursor)) = view {
+            self.scroll_offset.set(self.position_of(top));
+            self.cursor_line.set(self.position_of(cursor));
+        }
+        self.clamp_scroll();
+        if self.following {
+            self.scroll_to_bottom();
+        } else {
+            self.scroll_to_cursor();
+        }
+    }
+
+    /// Re-evaluates the filters for `content[from..]`, e.g. after lines were
+    /// appended.
+    fn extend_visible(&mut self, from: usize) {
+        if self.visible.borrow().is_none() {
+            return;
+        }
+        // Appended lines may continue the last entry, which is then redone.
+        let from = if self.folding {
+            self.entry_start(from.min(self.content.borrow().len().saturating_sub(1)))
+        } else {
+            from
+        };
+        let filtering = !self.filters.is_empty() || self.lua_filter.borrow().is_some();
+
+        // A failing predicate keeps its line so a bug in a script can't
+        // silently hide the log; the first error is reported.
+        let mut failures = 0;
+        let mut first_error = None;
+        let kept: Vec<usize> = {
+            let predicate = match self.lua_filter.borrow().as_ref() {
+                Some(key) => match self.lua.registry_value::<Function>(key) {
+                    Ok(predicate) => Some(predicate),
+                    Err(err) => {
+                        first_error = Some(err);
+                        None
+                    }
+                },
+                None => None,
+            };
+            let content = self.content.borrow();
+            let mut keeps = |index: usize, line: &str| {
+                if !self
+                    .filters
+                    .iter()
+                    .all(|filter| filter.keeps(line, &self.timestamps))
+                {
+                    return false;
+                }
+                let Some(predicate) = &predicate else {
+                    return true;
+                };
+                predicate
+                    .call::<_, bool>((line, index + 1))
+                    .unwrap_or_else(|err| {
+                        failures += 1;
+                        first_error.get_or_insert(err);
+                        true
+                    })
+            };
+            if self.folding {
+                // Whole entries are kept if any of their lines is, and show
+                // only their first line unless expanded.
+                let mut kept = Vec::new();
+                let mut start = from;
+                let mut entry_kept = false;
+                for index in from..=content.len() {
+                    let line = (index < content.len()).then(|| content.line(index));
+                    let starts_entry = line
+                        .as_ref()
+                        .is_none_or(|line| index == from || !self.is_continuation(line));
+                    if starts_entry && index > from && entry_kept {
+                        kept.push(start);
+                        if self.expanded.contains(&start) {
+                            kept.extend(start + 1..index);
+                        }
+                    }
+                    if let Some(line) = line {
+                        if starts_entry {
+                            start = index;
+                            entry_kept = false;
+                        }
+                        entry_kept = entry_kept || !filtering || keeps(index, &line);
+                    }
+                }
+                kept
+            } else {
+                (from..content.len())
+                    .filter(|&index| keeps(index, &content.line(index)))
+                    .collect()
+            }
+        };
+
+        if let Some(err) = first_error {
+            self.show_error(format!("filter: {failures} line(s) failed: {err}"));
+        }
+        if let Some(visible) = self.visible.borrow_mut().as_mut() {
+            visible.truncate(visible.partition_point(|&index| index < from));
+            visible.extend(kept);
+        }
+    }
+
+    fn execute_command(&mut self, command: &str) {
+        let (name, arg) = command
+            .trim()
+            .split_once(' ')
+            .map_or((command.trim(), ""), |(name, arg)| (name, arg.trim()));
+
+        match name {
+            "quit()" => self.should_quit = true,
+            "goto" => self.goto_line(arg),
+            "set" => self.set_option(arg),
+            "filter" => self.push_filter(arg, false),
+            "filter!" => self.push_filter(arg, true),
+            "since" => self.filter_since(arg),
+            "since!" => self.dim_before(arg),
+            "reload" => self.reload(),
+            "open" => self.open_file(arg),
+            "tabnew" => self.new_tab(arg),
+            "tabclose" => self.close_tab(),
+            "split" => self.split(Direction::Vertical, arg),
+            "vsplit" => self.split(Direction::Horizontal, arg),
+            "only" => self.close_split(),
+            "help" => self.open_help(),
+            "stats" => self.stats = Some(stats::Stats::default()),
+            "histogram" => self.show_histogram(arg),
+            "columns" => self.set_columns(arg),
+            "source" => self.source(arg),
+            "write" => self.write_view(arg, false),
+            "write!" => self.write_view(arg, true),
+            _ if self.commands.borrow().contains_key(name) => self.run_user_command(name, arg),
+            _ => self.run_lua(command, "command"),
+        }
+    }
+
+    /// Quits, asking first if there's a view worth keeping.
+    fn quit(&mut self) {
+        let busy = self.following
+            || self.new_lines.is_some()
+            || !self.filters.is_empty()
+            || self.lua_filter.borrow().is_some()
+            || self.search_regex.is_some();
+        if self.confirm_quit && busy {
+            self.pending_quit = true;
+            self.show_message("quit? (y/n)");
+        } else {
+            self.should_quit = true;
+        }
+    }
+
+    fn open_help(&mut self) {
+        self.show_help = true;
+        self.help_scroll = 0;
+    }
+
+    /// Completes the command line with the next candidate for the command
+    /// name, the options of `:set`, or the path argument of commands opening
+    /// or writing files.
+    fn complete_command(&mut self) {
+        let completion = match &mut self.completion {
+            Some(completion) => completion,
+            None => {
+                let candidates = match self.input_buffer.as_str().split_once(' ') {
+                    None => {
+                        let mut names: Vec<String> = help::COMMANDS
+                            .iter()
+                            .map(|(name, _, _)| name.to_string())
+                            .chain(self.commands.borrow().keys().cloned())
+                            .filter(|name| name.starts_with(self.input_buffer.as_str()))
+                            .collect();
+                        names.sort();
+                        names.dedup();
+                        names
+                    }
+                    Some((
+                        name @ ("open" | "tabnew" | "split" | "vsplit" | "source" | "write"
+                        | "write!"),
+                        arg,
+                    )) => complete_path(arg.trim_start())
+                        .into_iter()
+                        .map(|path| format!("{name} {path}"))
+                        .collect(),
+                    Some(("set", args)) => {
+                        // The last option is completed, after any others.
+                        let (before, word) = match args.rsplit_once(' ') {
+                            Some((before, word)) => (format!("{before} "), word),
+                            None => (String::new(), args),
+                        };
+                        let (no, word) = match word.strip_prefix("no") {
+                            Some(word) => ("no", word),
+                            None => ("", word),
+                        };
+                        options::names()
+                            .chain(["fold"])
+                            .filter(|name| name.starts_with(word))
+                            .map(|name| format!("set {before}{no}{name}"))
+                            .collect()
+                    }
+                    Some(_) => Vec::new(),
+                };
+                match candidates.len() {
+                    0 => return,
+                    1 => {
+                        // A unique match is final, so the next Tab completes
+                        // from there, e.g. inside a completed directory.
+                        self.input_buffer.set(candidates[0].clone());
+                        return;
+                    }
+                    _ => self.completion.insert(Completion {
+                        original: self.input_buffer.as_str().to_string(),
+                        candidates,
+                        index: usize::MAX,
+                    }),
+                }
+            }
+        };
+
+        completion.index = match completion.index {
+            usize::MAX => 0,
+            index => (index + 1) % (completion.candidates.len() + 1),
+        };
+        self.input_buffer.set(
+            completion
+                .candidates
+                .get(completion.index)
+                .unwrap_or(&completion.original)
+                .clone(),
+        );
+    }
+
+    /// Executes a Lua chunk, reporting errors in the status line.
+    fn run_lua(&mut self, source: &str, name: &str) {
+        self.with_script(|lua| lua.load(source).set_name(format!("={name}")).exec());
+    }
+
+    /// Calls a command registered with `logview.command`, passing the rest
+    /// of the command line as its argument.
+    fn run_user_command(&mut self, name: &str, arg: &str) {
+        let commands = self.commands.clone();
+        self.with_script(|lua| {
+            let function = match commands.borrow().get(name) {
+                Some(key) => lua.registry_value::<Function>(key)?,
+                None => return Ok(()),
+            };
+            function.call::<_, ()>(arg)
+        });
+    }
+
+    /// Runs `script`, reporting errors in the status line and applying any
+    /// changes it made through the `logview` API.
+    fn with_script(&mut self, script: impl FnOnce(&Lua) -> mlua::Result<()>) {
+        let offset = self.scroll_offset.get();
+        if let Err(err) = script(&self.lua) {
+            self.show_error(script::error_message(&err));
+        }
+        // Scripts may move the viewport; treat that like a manual scroll and
+        // keep it within the file.
+        if self.scroll_offset.get() != offset {
+            self.leave_bottom();
+        }
+        let on_line_changed = self.on_line_changed.replace(false);
+        if on_line_changed {
+            self.content.borrow_mut().clear_replaced();
+            self.run_line_hook(0);
+            self.restyle_from(0);
+        }
+        if self.lua_filter_changed.replace(false) || on_line_changed {
+            self.refilter();
+        }
+        self.clamp_scroll();
+        self.keep_cursor_on_screen();
+    }
+
+    /// Runs the `logview.on_line` hook on the lines from content index
+    /// `from` on, replacing those it returns a string for.
+    fn run_line_hook(&mut self, from: usize) {
+        // Most of the time there's no hook, so there's nothing to call.
+        if self.on_line.borrow().is_none() {
+            return;
+        }
+        if let Err(err) = self.try_run_line_hook(from) {
+            self.show_error(format!("on_line: {err}"));
+        }
+    }
+
+    fn try_run_line_hook(&self, from: usize) -> Result<(), String> {
+        let hook = match &*self.on_line.borrow() {
+            Some(key) => self
+                .lua
+                .registry_value::<Function>(key)
+                .map_err(|err| script::error_message(&err))?,
+            None => return Ok(()),
+        };
+
+        // The hook may read lines itself, so replacements are applied only
+        // once it has seen every line.
+        let mut replacements = Vec::new();
+        let mut failures = 0;
+        let mut first_error = None;
+        {
+            let content = self.content.borrow();
+            for index in from..content.len() {
+                let line = content.original_line(index).into_owned();
+                match hook.call::<_, Option<String>>((index + 1, line)) {
+                    Ok(replacement) => replacements.push((index, replacement)),
+                    // A failing line is left as is and the rest still run.
+                    Err(err) => {
+                        failures += 1;
+                        first_error.get_or_insert(err);
+                        replacements.push((index, None));
+                    }
+                }
+            }
+        }
+        let mut content = self.content.borrow_mut();
+        for (index, replacement) in replacements {
+            content.replace_line(index, replacement);
+        }
+        match first_error {
+            Some(err) => Err(format!(
+                "{failures} line(s) failed: {}",
+                script::error_message(&err)
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs the configured init script, or the default one if it exists.
+    fn run_init_script(&mut self, configured: Option<&Path>) {
+        let path = match configured {
+            Some(path) => path.to_path_buf(),
+            None => match dirs::home_dir() {
+                Some(home) if home.join(DEFAULT_INIT_SCRIPT).exists() => {
+                    home.join(DEFAULT_INIT_SCRIPT)
+                }
+                _ => return,
+            },
+        };
+
+        self.run_script_file(&path, "init script ");
+    }
+
+    /// Runs the Lua script at `path`, whose directory `require` then
+    /// searches first. Errors reading it are prefixed with `command`.
+    fn run_script_file(&mut self, path: &Path, command: &str) {
+        match fs::read_to_string(path) {
+            Ok(source) => {
+                if let Some(dir) = path.parent()
+                    && let Err(err) = script::add_to_path(&self.lua, dir)
+                {
+                    self.show_error(script::error_message(&err));
+                }
+                self.run_lua(&source, &path.display().to_string());
+            }
+            Err(err) => self.show_error(format!("{command}{}: {err}", path.display())),
+        }
+    }
+
+    /// Handles `:source <file>`, running a Lua script.
+    fn source(&mut self, arg: &str) {
+        if arg.is_empty() {
+            self.show_error("source: missing file name");
+            return;
+        }
+        self.run_script_file(&expand_path(arg), "source: ");
+    }
+
+    /// Handles `:columns <key>,...`, or `:columns` alone to show lines as
+    /// they are again.
+    fn set_columns(&mut self, spec: &str) {
+        self.columns = columns::Columns::new(spec);
+        self.horizontal_offset = 0;
+    }
+
+    /// Handles `:set <option>...`, or shows every option without any.
+    fn set_option(&mut self, args: &str) {
+        if args.is_empty() {
+            let mut values = self.options.describe_all();
+            values.push(if self.folding { "fold" } else { "nofold" }.to_string());
+            self.show_message(values.join(" "));
+            return;
+        }
+        for arg in args.split_whitespace() {
+            // Folding belongs to the tab and regroups its lines.
+            let folding = match arg {
+                "fold" => Some(true),
+                "nofold" => Some(false),
+                "fold!" => Some(!self.folding),
+                "fold?" => {
+                    self.show_message(if self.folding { "fold" } else { "nofold" });
+                    continue;
+                }
+                _ => None,
+            };
+            if let Some(enabled) = folding {
+                self.set_folding(enabled);
+                continue;
+            }
+
+            let ignore_case = self.options.ignore_case;
+            match self.options.set(arg) {
+                Ok(Some(value)) => self.show_message(value),
+                Ok(None) => {}
+                Err(err) => {
+                    self.show_error(err);
+                    return;
+                }
+            }
+            if self.options.ignore_case != ignore_case {
+                self.recompile_search();
+            }
+        }
+    }
+
+    /// Handles `:goto <n>`, where `n` is a 1-based line number.
+    fn goto_line(&mut self, arg: &str) {
+        let line = match arg.parse::<usize>() {
+            Ok(line) => line,
+            Err(_) => {
+                self.show_error(format!("goto: invalid line number '{arg}'"));
+                return;
+            }
+        };
+
+        let total = self.content.borrow().len();
+        if line == 0 || line > total {
+            self.show_error(format!("goto: line {line} out of range (1-{total})"));
+            return;
+        }
+
+        self.jump_to_line(line - 1);
+    }
+
+    /// Runs a normal mode `action`, repeated or given a line number by the
+    /// count typed before it.
+    fn perform(&mut self, action: Action) {
+        let count = self.take_count();
+        let times = count.unwrap_or(1);
+        match action {
+            Action::ScrollDown => self.cursor_down(times),
+            Action::ScrollUp => self.cursor_up(times),
+            Action::ScrollLeft => self.scroll_left(HORIZONTAL_SCROLL_STEP.saturating_mul(times)),
+            Action::ScrollRight => self.scroll_right(HORIZONTAL_SCROLL_STEP.saturating_mul(times)),
+            Action::PageDown => {
+                self.scroll_down(self.page_size().saturating_mul(times));
+                self.keep_cursor_on_screen();
+            }
+            Action::PageUp => {
+                self.scroll_up(self.page_size().saturating_mul(times));
+                self.keep_cursor_on_screen();
+            }
+            Action::GotoTop => match count {
+                Some(line) => self.jump_to_line_number(line),
+                None => self.scroll_to_top(),
+            },
+            Action::GotoBottom => match count {
+                Some(line) => self.jump_to_line_number(line),
+                None => {
+                    self.scroll_to_bottom();
+                    self.return_to_bottom();
+                }
+            },
+            Action::GotoPercent => match count {
+                Some(percent) => self.jump_to_percent(percent),
+                None => self.show_error("%: needs a count, e.g. 50%"),
+            },
+            Action::ToggleFollow => self.toggle_follow(),
+            Action::Search => {
+                self.input_mode = InputMode::Search;
+                self.input_buffer.clear();
+                self.search_history.reset();
+                self.search_origin = self.current_position();
+            }
+            Action::SearchNext => self.search_next(true),
+            Action::SearchPrevious => self.search_next(false),
+            Action::SearchWord => self.search_word(true),
+            Action::SearchWordBackward => self.search_word(false),
+            Action::FilterWord => {
+                if let Some(query) = self.word_query() {
+                    self.push_filter(&query, false);
+                }
+            }
+            Action::ClearFilters => self.clear_filter(),
+            Action::PopFilter => self.pop_filter(),
+            Action::CopyLine => {
+                let position = self.current_position();
+                self.copy_lines(position, position);
+            }
+            Action::VisualMode => {
+                if self.visible_len() > 0 {
+                    self.input_mode = InputMode::Visual;
+                    self.visual_anchor = self.current_position();
+                    self.visual_cursor = self.visual_anchor;
+                }
+            }
+            Action::ToggleBookmark => self.toggle_bookmark(),
+            Action::NextBookmark => self.jump_to_bookmark(true),
+            Action::PreviousBookmark => self.jump_to_bookmark(false),
+            Action::NextError => self.jump_to_level(true, Level::Error, false),
+            Action::PreviousError => self.jump_to_level(false, Level::Error, false),
+            Action::NextWarning => self.jump_to_level(true, Level::Warn, true),
+            Action::PreviousWarning => self.jump_to_level(false, Level::Warn, true),
+            Action::SetMark | Action::JumpToMark => self.awaiting_argument = Some(action),
+            Action::ToggleEntry => {
+                if !self.toggle_entry() {
+                    self.show_detail();
+                }
+            }
+            Action::Reload => self.reload(),
+            Action::NextTab => self.next_tab(true, count),
+            Action::PreviousTab => self.next_tab(false, count),
+            Action::FocusOtherPane => self.focus_other_pane(),
+            Action::Command => {
+                self.input_mode = InputMode::Command;
+                self.input_buffer.clear();
+                self.command_history.reset();
+            }
+            Action::RepeatCommand => match self.last_command.clone() {
+                Some(command) => self.execute_command(&command),
+                None => self.show_message("no previous command"),
+            },
+            Action::Palette => self.open_palette(),
+            Action::Help => self.open_help(),
+            Action::Quit => self.quit(),
+        }
+    }
+
+    /// Takes the count typed before a command, if any. Counts too large to
+    /// represent saturate.
+    fn take_count(&mut self) -> Option<usize> {
+        if self.count_buffer.is_empty() {
+            return None;
+        }
+        let count = self.count_buffer.parse().unwrap_or(usize::MAX);
+        self.count_buffer.clear();
+        Some(count)
+    }
+
+    /// Jumps to the 1-based `line`, clamped to the file, for `{count}G`.
+    fn jump_to_line_number(&mut self, line: usize) {
+        let total = self.content.borrow().len();
+        self.jump_to_line(line.clamp(1, total.max(1)) - 1);
+    }
+
+    /// Handles `{count}%`, going to the line `percent` of the way through
+    /// the file, as in vim.
+    fn jump_to_percent(&mut self, percent: usize) {
+        let total = self.content.borrow().len();
+        self.jump_to_line_number((percent.min(100) * total).div_ceil(100));
+    }
+
+    /// Makes the 0-based `line` (or the next visible line, when filtered)
+    /// the current line, see `jump_to_position`.
+    fn jump_to_line(&mut self, line: usize) {
+        self.jump_to_position(self.position_of(line));
+    }
+
+    /// Makes `position` the current line, scrolling it to the top of the
+    /// viewport unless it's already on screen.
+    fn jump_to_position(&mut self, position: usize) {
+        self.leave_bottom();
+        let position = position.min(self.max_scroll_offset());
+        if !self.is_on_screen(position) {
+            self.scroll_offset.set(position);
+        }
+        self.cursor_line.set(position);
+    }
+
+    /// Makes `position` the current line, scrolling as little as possible
+    /// to keep it on screen.
+    fn move_cursor(&mut self, position: usize) {
+        self.cursor_line.set(position.min(self.max_scroll_offset()));
+        self.scroll_to_cursor();
+    }
+
+    fn cursor_down(&mut self, lines: usize) {
+        self.move_cursor(self.current_position().saturating_add(lines));
+        if self.current_position() + 1 >= self.visible_len() {
+            self.return_to_bottom();
+        }
+    }
+
+    fn cursor_up(&mut self, lines: usize) {
+        self.leave_bottom();
+        self.move_cursor(self.current_position().saturating_sub(lines));
+    }
+
+    /// Scrolls just enough for the current line to be on screen.
+    fn scroll_to_cursor(&mut self) {
+        let cursor = self.current_position();
+        // Lines take at least a row each, so only the screenful above the
+        // cursor can share the screen with it.
+        let mut top = self
+            .scroll_offset
+            .get()
+            .min(cursor)
+            .max((cursor + 1).saturating_sub(self.viewport_height.max(1)));
+        if self.options.wrap {
+            let mut rows: usize = (top..=cursor)
+                .map(|position| self.line_rows(position))
+                .sum();
+            while rows > self.viewport_height && top < cursor {
+                rows -= self.line_rows(top);
+                top += 1;
+            }
+        }
+        self.scroll_offset.set(top);
+    }
+
+    /// Keeps the current line on screen in each pane after the terminal was
+    /// resized, once the viewport has been measured at the new size.
+    fn handle_resize(&mut self) {
+        for _ in 0..if self.split.is_some() { 2 } else { 1 } {
+            self.clamp_scroll();
+            self.horizontal_offset = self.horizontal_offset.min(self.max_horizontal_offset());
+            if self.following {
+                self.scroll_to_bottom();
+            } else {
+                self.scroll_to_cursor();
+            }
+            self.swap_panes();
+        }
+    }
+
+    /// Moves the current line onto the screen after it was scrolled, to
+    /// its first or last line.
+    fn keep_cursor_on_screen(&mut self) {
+        let top = self.scroll_offset.get();
+        let bottom = self.bottom_position();
+        let cursor = self.cursor_line.get();
+        self.cursor_line.set(cursor.clamp(top, bottom));
+    }
+
+    /// View position of the last line wholly on screen, or the top line if
+    /// even that doesn't fit.
+    fn bottom_position(&self) -> usize {
+        let top = self.scroll_offset.get();
+        let mut rows = 0;
+        let lines = (top..self.visible_len())
+            .take(self.viewport_height.max(1))
+            .take_while(|&position| {
+                rows += self.line_rows(position);
+                rows <= self.viewport_height
+            })
+            .count();
+        (top + lines.max(1) - 1).min(self.max_scroll_offset())
+    }
+
+    fn is_on_screen(&self, position: usize) -> bool {
+        (self.scroll_offset.get()..=self.bottom_position()).contains(&position)
+    }
+
+    /// Lines moved by a page motion, keeping one line of context on screen.
+    fn page_size(&self) -> usize {
+        if !self.options.wrap {
+            return self.viewport_height.saturating_sub(1).max(1);
+        }
+        let mut rows = 0;
+        let lines = (self.scroll_offset.get()..self.visible_len())
+            .take_while(|&position| {
+                rows += self.line_rows(position);
+                rows <= self.viewport_height
+            })
+            .count();
+        lines.saturating_sub(1).max(1)
+    }
+
+    /// Screen rows taken by the line at view `position`.
+    fn line_rows(&self, position: usize) -> usize {
+        if !self.options.wrap || self.viewport_width == 0 {
+            return 1;
+        }
+        let width = self.display_width(position);
+        width.div_ceil(self.viewport_width).max(1)
+    }
+
+    /// Columns taken by the line at view `position` as shown, with escape
+    /// sequences removed, tabs expanded and, with `:set list`, control
+    /// characters and carriage return line endings in caret notation.
+    fn display_width(&self, position: usize) -> usize {
+        let content = self.content.borrow();
+        let index = self.line_at(position);
+        let line = self.display(content.line(index));
+        let (text, _) = ansi::parse(&line);
+        let width = text.chars().fold(0, |column, c| match c {
+            '\t' => next_tab_stop(column, self.options.tab_width),
+            c if self.options.list && caret_notation(c).is_some() => column + 2,
+            _ => column + 1,
+        });
+        if self.options.list && content.has_carriage_return(index) {
+            width + 2
+        } else {
+            width
+        }
+    }
+
+    /// The current inputs of `style_line` other than the line itself.
+    fn style_key(&self) -> StyleKey {
+        StyleKey {
+            search: self
+                .active_search()
+                .map(|regex| (regex.as_str().to_string(), regex.is_case_insensitive())),
+            highlights: self
+                .highlights
+                .borrow()
+                .iter()
+                .map(|highlight| (highlight.regex.as_str().to_string(), highlight.color))
+                .collect(),
+            level_coloring: self.options.level_coloring,
+            syslog: self.is_syslog(),
+            json: self.options.json,
+            columns: self
+                .columns
+                .as_ref()
+                .map(|columns| (columns.keys().to_vec(), columns.widths())),
+            tab_width: self.options.tab_width,
+            list: self.options.list,
+            dim_before: self.dim_before,
+        }
+    }
+
+    /// Drops the styled lines from content index `first_changed` on, whose
+    /// text changed.
+    fn restyle_from(&mut self, first_changed: usize) {
+        self.style_cache
+            .lines
+            .retain(|&index, _| index < first_changed);
+        let matches = &mut self.match_count;
+        matches.lines.retain(|&index| index < first_changed);
+        matches.scanned = matches.scanned.min(first_changed);
+    }
+
+    /// Counts the lines matching the search the count hasn't covered yet,
+    /// starting over if the search changed.
+    fn update_match_count(&mut self) {
+        let query = self
+            .search_regex
+            .as_ref()
+            .map(|regex| (regex.as_str().to_string(), regex.is_case_insensitive()));
+        if self.match_count.query != query {
+            self.match_count = MatchCount {
+                query,
+                ..MatchCount::default()
+            };
+        }
+        let Some(regex) = &self.search_regex else {
+            return;
+        };
+        let content = self.content.borrow();
+        let matches = &mut self.match_count;
+        for index in matches.scanned..content.len() {
+            if regex.is_match(&content.line(index)) {
+                matches.lines.push(index);
+            }
+        }
+        matches.scanned = content.len();
+    }
+
+    /// The number of the match nearest the current line, counting from 1,
+    /// and how many lines in view match the search, if there is one.
+    fn match_position(&self) -> Option<(usize, usize)> {
+        self.search_regex.as_ref()?;
+        let lines = &self.match_count.lines;
+        let visible = self.visible.borrow();
+        let shown: Cow<[usize]> = match visible.as_ref() {
+            Some(visible) => Cow::Owned(
+                lines
+                    .iter()
+                    .copied()
+                    .filter(|index| visible.binary_search(index).is_ok())
+                    .collect(),
+            ),
+            None => Cow::Borrowed(lines),
+        };
+        if shown.is_empty() {
+            return Some((0, 0));
+        }
+        let current = self.line_at(self.current_position());
+        let after = shown.partition_point(|&index| index < current);
+        // The match on the current line, or else the closer one around it.
+        let nearest = match (after.checked_sub(1), shown.get(after)) {
+            (_, Some(&next)) if next == current => after,
+            (Some(before), Some(&next)) if next - current < current - shown[before] => after,
+            (Some(before), _) => before,
+            (None, _) => after,
+        };
+        Some((nearest + 1, shown.len()))
+    }
+
+    /// Time of the timestamp the line at view `position` starts with, as
+    /// shown.
+    fn timestamp_at(&self, position: usize) -> Option<i64> {
+        let content = self.content.borrow();
+        self.timestamps
+            .parse(&self.display(content.line(self.line_at(position))))
+    }
+
+    /// Time of the closest timestamped line above view `position`, looking
+    /// back a limited way.
+    fn previous_timestamp(&self, position: usize) -> Option<i64> {
+        (position.saturating_sub(RELTIME_LOOKBACK)..position)
+            .rev()
+            .find_map(|position| self.timestamp_at(position))
+    }
+
+    /// Returns `line` as it's shown, which differs from the file for
+    /// structured lines with `:columns` or `:set json`.
+    fn display<'a>(&self, line: Cow<'a, str>) -> Cow<'a, str> {
+        if let Some(columns) = &self.columns
+            && let Some(formatted) = columns.format(&line, self.is_syslog())
+        {
+            return Cow::Owned(formatted);
+        }
+        match self.options.json.then(|| json::summarize(&line)).flatten() {
+            Some(summary) => Cow::Owned(summary),
+            None => line,
+        }
+    }
+
+    /// View position of the current line, the one relative line numbers
+    /// count from.
+    fn current_position(&self) -> usize {
+        self.cursor_line.get().min(self.max_scroll_offset())
+    }
+
+    fn clamp_scroll(&mut self) {
+        self.scroll_offset
+            .set(self.scroll_offset.get().min(self.max_scroll_offset()));
+        self.cursor_line.set(self.current_position());
+    }
+
+    fn max_scroll_offset(&self) -> usize {
+        self.visible_len().saturating_sub(1)
+    }
+
+    fn scroll_down(&mut self, lines: usize) {
+        self.scroll_offset.set(
+            self.scroll_offset
+                .get()
+                .saturating_add(lines)
+                .min(self.max_scroll_offset()),
+        );
+        if self.scroll_offset.get() >= self.max_scroll_offset() {
+            self.return_to_bottom();
+        }
+    }
+
+    fn scroll_up(&mut self, lines: usize) {
+        self.leave_bottom();
+        self.scroll_offset
+            .set(self.scroll_offset.get().saturating_sub(lines));
+    }
+
+    fn scroll_left(&mut self, columns: usize) {
+        self.horizontal_offset = self
+            .horizontal_offset
+            .min(self.max_horizontal_offset())
+            .saturating_sub(columns);
+    }
+
+    fn scroll_right(&mut self, columns: usize) {
+        self.horizontal_offset = self
+            .horizontal_offset
+            .saturating_add(columns)
+            .min(self.max_horizontal_offset());
+    }
+
+    /// Horizontal offset at which the longest line on screen ends at the
+    /// right edge.
+    fn max_horizontal_offset(&self) -> usize {
+        if self.options.wrap {
+            return 0;
+        }
+        let top = self.scroll_offset.get();
+        let bottom = top
+            .saturating_add(self.viewport_height)
+            .min(self.visible_len());
+        let longest = (top..bottom)
+            .map(|position| self.display_width(position))
+            .max()
+            .unwrap_or(0);
+        longest.saturating_sub(self.viewport_width)
+    }
+
+    fn scroll_to_top(&mut self) {
+        self.leave_bottom();
+        self.scroll_offset.set(0);
+        self.cursor_line.set(0);
+    }
+
+    /// Stops scrolling to new lines after moving away from the end while
+    /// following, counting them instead until back at the end.
+    fn leave_bottom(&mut self) {
+        if self.following {
+            self.following = false;
+            self.new_lines = Some(0);
+        }
+    }
+
+    /// Scrolls to new lines again on coming back to the end, if following
+    /// was paused by moving away from it.
+    fn return_to_bottom(&mut self) {
+        if self.new_lines.take().is_some() {
+            self.following = true;
+            self.scroll_to_bottom();
+        }
+    }
+
+    /// Scrolls so the last line sits at the bottom of the viewport, and
+    /// makes it the current line.
+    fn scroll_to_bottom(&mut self) {
+        let len = self.visible_len();
+        self.cursor_line.set(len.saturating_sub(1));
+        if !self.options.wrap {
+            self.scroll_offset
+                .set(len.saturating_sub(self.viewport_height));
+            return;
+        }
+        // Walk back from the end until the next line wouldn't fit.
+        let mut rows = 0;
+        let mut top = len;
+        while top > 0 {
+            rows += self.line_rows(top - 1);
+            if rows > self.viewport_height && top < len {
+                break;
+            }
+            top -= 1;
+        }
+        self.scroll_offset.set(top);
+    }
+
+    /// Adds or removes a bookmark on the current line.
+    fn toggle_bookmark(&mut self) {
+        if self.visible_len() == 0 {
+            return;
+        }
+        let line = self.line_at(self.current_position());
+        match self.bookmarks.binary_search(&line) {
+            Ok(i) => {
+                self.bookmarks.remove(i);
+                self.show_message(format!("removed bookmark on line {}", line + 1));
+            }
+            Err(i) => {
+                self.bookmarks.insert(i, line);
+                self.show_message(format!("bookmarked line {}", line + 1));
+            }
+        }
+    }
+
+    /// Sets mark `name` on the current line.
+    fn set_mark(&mut self, name: char) {
+        if self.visible_len() == 0 {
+            return;
+        }
+        let line = self.line_at(self.current_position());
+        self.marks.insert(name, line);
+        self.show_message(format!("mark '{name}' set on line {}", line + 1));
+    }
+
+    fn jump_to_mark(&mut self, name: char) {
+        match self.marks.get(&name) {
+            Some(&line) => self.jump_to_line(line),
+            None => self.show_error(format!("mark '{name}' not set")),
+        }
+    }
+
+    /// Jumps to the next (or previous) bookmarked line that isn't filtered
+    /// out, wrapping around the ends.
+    fn jump_to_bookmark(&mut self, forward: bool) {
+        let shown: Vec<usize> = self
+            .bookmarks
+            .iter()
+            .copied()
+            .filter(|&line| {
+                let position = self.position_of(line);
+                position < self.visible_len() && self.line_at(position) == line
+            })
+            .collect();
+        if shown.is_empty() {
+            self.show_error("no bookmarks");
+            return;
+        }
+
+        let current = self.line_at(self.current_position());
+        let target = if forward {
+            shown.iter().find(|&&line| line > current).or(shown.first())
+        } else {
+            shown
+                .iter()
+                .rev()
+                .find(|&&line| line < current)
+                .or(shown.last())
+        };
+        if let Some(&line) = target {
+            self.jump_to_line(line);
+        }
+    }
+
+    /// Copies the lines at view positions `first..=last` to the clipboard,
+    /// without their ANSI escapes.
+    fn copy_lines(&mut self, first: usize, last: usize) {
+        if self.visible_len() == 0 {
+            self.show_error("copy: no line to copy");
+            return;
+        }
+
+        let text = {
+            let content = self.content.borrow();
+            let lines: Vec<String> = (first..=last)
+                .map(|position| {
+                    let line = content.line(self.line_at(position));
+                    ansi::parse(&line).0.into_owned()
+                })
+                .collect();
+            lines.join("\n")
+        };
+        match clipboard::copy(&text) {
+            Ok(()) if first == last => {
+                let line = self.line_at(first) + 1;
+                self.show_message(format!("copied line {line}"));
+            }
+            Ok(()) => self.show_message(format!("copied {} lines", last - first + 1)),
+            Err(err) => self.show_error(format!("copy: {err}")),
+        }
+    }
+
+    /// Handles `:write <path>`, saving the lines in view, including those
+    /// of collapsed entries. Existing files are only replaced by `:write!`.
+    fn write_view(&mut self, arg: &str, overwrite: bool) {
+        if arg.is_empty() {
+            self.show_error("write: missing file name");
+            return;
+        }
+        let path = expand_path(arg);
+        if !overwrite && path.exists() {
+            self.show_error(format!(
+                "write: {} exists (use :write! to overwrite)",
+                path.display()
+            ));
+            return;
+        }
+
+        let mut written = 0;
+        let result = File::create(&path).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            let content = self.content.borrow();
+            for position in 0..self.visible_len() {
+                let index = self.line_at(position);
+                let end = self.collapsed_entry(index).unwrap_or(index + 1);
+                for index in index..end {
+                    writeln!(writer, "{}", content.line(index))?;
+                    written += 1;
+                }
+            }
+            writer.flush()
+        });
+        match result {
+            Ok(()) => self.show_message(format!("wrote {written} lines to {}", path.display())),
+            Err(err) => self.show_error(format!("write: {}: {err}", path.display())),
+        }
+    }
+
+    /// View positions of the first and last selected lines.
+    fn visual_selection(&self) -> (usize, usize) {
+        (
+            self.visual_anchor.min(self.visual_cursor),
+            self.visual_anchor.max(self.visual_cursor),
+        )
+    }
+
+    /// Moves the end of the visual selection, scrolling to keep it on
+    /// screen.
+    fn move_visual_cursor(&mut self, delta: isize) {
+        let cursor = self
+            .visual_cursor
+            .saturating_add_signed(delta)
+            .min(self.visible_len().saturating_sub(1));
+        self.visual_cursor = cursor;
+        self.move_cursor(cursor);
+    }
+
+    /// Handles `r` and `:reload`.
+    fn reload(&mut self) {
+        let Some(path) = self.file_path.clone() else {
+            self.show_error("reload: no file to reload");
+            return;
+        };
+        self.start_loading(path, LoadKind::Reload);
+    }
+
+    /// Brings `content` up to date after the watcher saw the file change.
+    fn handle_file_change(&mut self, change: watch::Change) {
+        // A mapping of the file can't be read past its new end, and the old
+        // content stays in view while it's reloaded.
+        if let Some(path) = &self.file_path
+            && let Ok(metadata) = fs::metadata(path)
+            && self
+                .content
+                .borrow()
+                .byte_len()
+                .is_some_and(|len| metadata.len() < len)
+        {
+            self.content.borrow_mut().unmap();
+        }
+        if let Some(loading) = &mut self.loading {
+            loading.pending_change = loading.pending_change.max(Some(change));
+            return;
+        }
+        match change {
+            watch::Change::Appended => self.poll_file(),
+            watch::Change::Rewritten => {
+                if let Some(path) = self.file_path.clone() {
+                    self.start_loading(path, LoadKind::Refresh);
+                }
+            }
+        }
+    }
+
+    /// Handles `:open <path>`, replacing the content with another file.
+    /// The current file stays open if the new one can't be read.
+    fn open_file(&mut self, arg: &str) {
+        if arg.is_empty() {
+            self.show_error("open: missing file name");
+            return;
+        }
+        let path = expand_path(arg);
+        if !self.confirm_binary(&path, OpenIn::Current) {
+            self.start_loading(path, LoadKind::Open);
+        }
+    }
+
+    /// Asks whether to open `path` anyway if it looks binary, and returns
+    /// whether it did. The file is opened `open_in` on y.
+    fn confirm_binary(&mut self, path: &Path, open_in: OpenIn) -> bool {
+        if !content::looks_binary(path, self.load_options.encoding).unwrap_or(false) {
+            return false;
+        }
+        self.show_message(format!(
+            "{} looks like a binary file. Open anyway? (y/n)",
+            path.display()
+        ));
+        self.pending_binary = Some((path.to_path_buf(), open_in));
+        true
+    }
+
+    /// Starts loading `path` in the background, replacing any load in
+    /// progress. The current content stays until it's done.
+    fn start_loading(&mut self, path: PathBuf, kind: LoadKind) {
+        self.loading = Some(Loading {
+            loader: Loader::spawn(path, self.load_options),
+            kind,
+            pending_change: None,
+        });
+    }
+
+    /// Shows the lines of the initial load as they're indexed, and swaps
+    /// in the content of a finished load.
+    fn poll_loading(&mut self) {
+        let Some(loading) = &mut self.loading else {
+            return;
+        };
+        let result = loading.loader.poll();
+        // Other loads keep the current content until they're done.
+        let first_changed = (loading.kind == LoadKind::Initial)
+            .then(|| loading.loader.take_indexed(&mut self.content.borrow_mut()))
+            .flatten();
+        if let Some(first_changed) = first_changed {
+            self.run_line_hook(first_changed);
+            self.restyle_from(first_changed);
+            self.extend_visible(first_changed);
+            if self.following {
+                self.scroll_to_bottom();
+            }
+        }
+        let Some(result) = result else {
+            return;
+        };
+        let Some(Loading {
+            loader,
+            kind,
+            pending_change,
+        }) = self.loading.take()
+        else {
+            return;
+        };
+
+        let mut content = match result {
+            Ok(content) => content,
+            Err(err) => {
+                let command = match kind {
+                    LoadKind::Initial => "",
+                    LoadKind::Open => "open: ",
+                    LoadKind::Reload | LoadKind::Refresh => "reload: ",
+                };
+                self.show_error(format!("{command}{}: {err}", loader.path.display()));
+                return;
+            }
+        };
+
+        if kind == LoadKind::Open
+            && let Err(err) = self.save_session()
+        {
+            self.show_error(format!("session: {err}"));
+        }
+        // The session is restored unless lines shown while loading were
+        // already scrolled through.
+        let restore = self.restore_session
+            && match kind {
+                LoadKind::Initial => self.scroll_offset.get() == 0 && self.cursor_line.get() == 0,
+                LoadKind::Open => true,
+                LoadKind::Reload | LoadKind::Refresh => false,
+            };
+
+        // Rereading the same file keeps the line at the top of the viewport
+        // and the current line in place if they still exist, as does
+        // finishing the initial load.
+        let top = (kind != LoadKind::Open && self.visible_len() > 0).then(|| {
+            (
+                self.line_at(self.scroll_offset.get().min(self.max_scroll_offset())),
+                self.line_at(self.current_position()),
+            )
+        });
+        // Bookmarks, marks and expanded entries can only be trusted on the
+        // same file with as many lines.
+        if kind == LoadKind::Open || content.len() != self.content.borrow().len() {
+            self.bookmarks.clear();
+            self.marks.clear();
+            self.expanded.clear();
+        }
+        if kind == LoadKind::Open {
+            self.syslog = None;
+            self.filetype_applied = false;
+            self.following = false;
+            self.new_lines = None;
+            self.scroll_offset.set(0);
+            self.cursor_line.set(0);
+            self.horizontal_offset = 0;
+            self.watcher = Some(watch::Watcher::new(loader.path.clone()));
+            self.file_path = Some(loader.path.clone());
+        }
+        // The initial load's lines were run through the hook as they were
+        // indexed; the rest are new or reread.
+        let hooked = if kind == LoadKind::Initial {
+            let mut indexed = self.content.borrow_mut();
+            content.take_replaced(&mut indexed);
+            indexed.len()
+        } else {
+            0
+        };
+        self.content.replace(content);
+        self.run_line_hook(hooked);
+        self.restyle_from(0);
+        self.refilter();
+        if let Some((top, cursor)) = top {
+            self.scroll_offset.set(self.position_of(top));
+            self.cursor_line.set(self.position_of(cursor));
+            self.clamp_scroll();
+        }
+        if restore {
+            self.load_session();
+        }
+        if self.following {
+            self.scroll_to_bottom();
+        }
+
+        let lines = self.content.borrow().len();
+        let message = match kind {
+            LoadKind::Open => Some(format!("{}: {lines} lines", loader.path.display())),
+            LoadKind::Reload => Some(format!("reloaded {lines} lines")),
+            LoadKind::Initial | LoadKind::Refresh => None,
+        };
+        let message = match (message, utf8_note(&self.content.borrow())) {
+            (Some(message), Some(note)) => Some(format!("{message} ({note})")),
+            (message, note) => message.or(note),
+        };
+        if let Some(message) = message {
+            self.show_message(message);
+        }
+        if let Some(change) = pending_change {
+            self.handle_file_change(change);
+        }
+    }
+
+    /// Remembers the position, bookmarks and marks in the current file for
+    /// the next time it's opened.
+    fn save_session(&self) -> io::Result<()> {
+        let Some(path) = &self.file_path else {
+            return Ok(());
+        };
+        let (top, cursor) = if self.visible_len() > 0 {
+            (
+                self.line_at(self.scroll_offset.get().min(self.max_scroll_offset())),
+                self.line_at(self.current_position()),
+            )
+        } else {
+            (0, 0)
+        };
+        let session =
+            session::Session::new(top, cursor, self.bookmarks.clone(), self.marks.clone());
+        session::save(path, session)
+    }
+
+    /// Checks on the loading and changes of the current file, returning
+    /// whether there's something new to draw.
+    fn poll_files(&mut self) -> bool {
+        // Progress, or the loaded content, needs drawing.
+        let mut dirty = self.loading.is_some();
+        self.poll_loading();
+        self.apply_filetype();
+        self.detect_format();
+        if let Some(change) = self.watcher.as_ref().and_then(watch::Watcher::poll) {
+            self.handle_file_change(change);
+            dirty = true;
+        }
+        dirty
+    }
+
+    /// Counts the lines in view the `:stats` overlay hasn't yet.
+    fn update_stats(&mut self) {
+        let Some(mut stats) = self.stats.take() else {
+            return;
+        };
+        let visible_len = self.visible_len();
+        // Fewer lines than counted means the file was truncated or reread.
+        if stats.counted > visible_len {
+            stats = stats::Stats::default();
+        }
+        let syslog = self.is_syslog();
+        let content = self.content.borrow();
+        for position in stats.counted..visible_len {
+            let line = content.line(self.line_at(position));
+            stats.add(line_level(&line, syslog), self.timestamps.parse(&line));
+        }
+        drop(content);
+        self.stats = Some(stats);
+    }
+
+    /// Handles `:histogram <pattern> [bucket]`, charting the lines in view
+    /// matching `pattern` per time bucket, 1m by default, or per `bucket`
+    /// lines if it's a plain number or no line has a timestamp.
+    fn show_histogram(&mut self, arg: &str) {
+        let (pattern, bucket) = match arg.rsplit_once(' ') {
+            Some((pattern, bucket))
+                if bucket.parse::<usize>().is_ok()
+                    || timestamp::parse_duration(bucket).is_some() =>
+            {
+                (pattern.trim_end(), Some(bucket))
+            }
+            _ => (arg, None),
+        };
+        if pattern.is_empty() {
+            self.show_error("histogram: needs a pattern");
+            return;
+        }
+        let regex = match self.compile_search(pattern) {
+            Ok(regex) => regex,
+            Err(err) => {
+                self.show_error(err.to_string());
+                return;
+            }
+        };
+        let lines = bucket.and_then(|bucket| bucket.parse::<usize>().ok());
+        let millis = match lines {
+            Some(_) => None,
+            None => Some(
+                bucket
+                    .and_then(timestamp::parse_duration)
+                    .unwrap_or(60 * 1000),
+            ),
+        };
+        if lines == Some(0) || millis == Some(0) {
+            self.show_error("histogram: the bucket must not be empty");
+            return;
+        }
+        if self.visible_len() == 0 {
+            self.show_message("histogram: no lines in view");
+            return;
+        }
+
+        // Positions of the matching lines, and the time of the line or,
+        // without one, of the last line before it with one.
+        let content = self.content.borrow();
+        let mut time = None;
+        let mut matches = Vec::new();
+        for position in 0..self.visible_len() {
+            let line = content.line(self.line_at(position));
+            time = self.timestamps.parse(&line).or(time);
+            if regex.is_match(&line) {
+                matches.push((position, time));
+            }
+        }
+        drop(content);
+
+        let timed: Vec<i64> = matches.iter().filter_map(|&(_, time)| time).collect();
+        let histogram = match millis.filter(|_| !timed.is_empty()) {
+            Some(millis) => {
+                let first = timed.iter().min().unwrap().div_euclid(millis);
+                let last = timed.iter().max().unwrap().div_euclid(millis);
+                if last - first >= MAX_HISTOGRAM_BUCKETS {
+                    self.show_error(format!(
+                        "histogram: over {MAX_HISTOGRAM_BUCKETS} buckets; try a longer one"
+                    ));
+                    return;
+                }
+                let mut counts = vec![0; (last - first + 1) as usize];
+                for time in timed {
+                    counts[(time.div_euclid(millis) - first) as usize] += 1;
+                }
+                histogram::Histogram {
+                    title: format!("{pattern} per {}", bucket.unwrap_or("1m")),
+                    counts,
+                    labels: (first..=last)
+                        .map(|bucket| self.timestamps.format_local(bucket * millis))
+                        .collect(),
+                }
+            }
+            None => {
+                let size = lines.unwrap_or_else(|| self.visible_len().div_ceil(200).max(1));
+                let buckets = self.visible_len().div_ceil(size).max(1);
+                let mut counts = vec![0; buckets];
+                for (position, _) in matches {
+                    counts[position / size] += 1;
+                }
+                histogram::Histogram {
+                    title: match size {
+                        1 => format!("{pattern} per line"),
+                        _ => format!("{pattern} per {size} lines"),
+                    },
+                    counts,
+                    labels: (0..buckets)
+                        .map(|bucket| format!("line {}", self.line_at(bucket * size) + 1))
+                        .collect(),
+                }
+            }
+        };
+        self.histogram = Some(histogram);
+    }
+
+    fn is_syslog(&self) -> bool {
+        self.syslog == Some(true)
+    }
+
+    /// Applies the current file's profile from the config's `filetypes`:
+    /// the one given with `--filetype`, else the one for its extension,
+    /// else, once its first line is loaded, the one matching that.
+    fn apply_filetype(&mut self) {
+        if self.filetype_applied || self.filetypes.is_empty() {
+            return;
+        }
+        let mut name = self.filetype.clone().or_else(|| {
+            let path = self.file_path.as_ref()?;
+            filetype::by_extension(&self.filetypes, path).map(str::to_string)
+        });
+        if name.is_none() {
+            let content = self.content.borrow();
+            if content.len() == 0 && self.loading.is_some() {
+                return;
+            }
+            let first_line = (content.len() > 0).then(|| content.line(0).into_owned());
+            drop(content);
+            if let Some(line) = first_line {
+                match filetype::by_first_line(&self.filetypes, &line) {
+                    Ok(found) => name = found.map(str::to_string),
+                    Err(err) => self.show_error(err),
+                }
+            }
+        }
+        self.filetype_applied = true;
+        let Some(filetype) = name.and_then(|name| self.filetypes.get(&name)).cloned() else {
+            return;
+        };
+        if let Some(format) = &filetype.format
+            && self.format.is_none()
+        {
+            match format.parse::<syslog::Format>() {
+                Ok(format) => self.syslog = Some(format == syslog::Format::Syslog),
+                Err(err) => self.show_error(format!("filetypes: {err}")),
+            }
+        }
+        if !filetype.set.is_empty() {
+            self.set_option(&filetype.set);
+        }
+    }
+
+    /// Decides whether the current file is syslog, from `--format` or once
+    /// enough of it is loaded to tell.
+    fn detect_format(&mut self) {
+        if self.syslog.is_some() {
+            return;
+        }
+        self.syslog = match self.format {
+            Some(format) => Some(format == syslog::Format::Syslog),
+            None => {
+                let content = self.content.borrow();
+                if content.len() < syslog::DETECT_LINES && self.loading.is_some() {
+                    return;
+                }
+                // Enough lines for the sample even with some blank ones.
+                let sample = content.len().min(syslog::DETECT_LINES * 5);
+                Some(syslog::detect((0..sample).map(|index| content.line(index))))
+            }
+        };
+    }
+
+    /// Saves the session of every tab.
+    fn save_sessions(&mut self) -> io::Result<()> {
+        for index in 0..self.buffers.len() {
+            // A tab never shown hasn't restored its session yet.
+            if index != self.active
+                && self.buffers[index]
+                    .loading
+                    .as_ref()
+                    .is_some_and(|loading| loading.kind == LoadKind::Initial)
+            {
+                continue;
+            }
+            self.switch_tab(index);
+            self.save_session()?;
+        }
+        Ok(())
+    }
+
+    /// Restores the session saved for the current file, if any.
+    fn load_session(&mut self) {
+        let Some(session) = self.file_path.as_deref().and_then(session::load) else {
+            return;
+        };
+        let len = self.content.borrow().len();
+        self.bookmarks = session.bookmarks;
+        self.bookmarks.retain(|&line| line < len);
+        self.bookmarks.sort_unstable();
+        self.bookmarks.dedup();
+        self.marks = session.marks;
+        self.marks.retain(|_, line| *line < len);
+        if len > 0 && !self.following {
+            self.scroll_offset
+                .set(self.position_of(session.top.min(len - 1)));
+            self.cursor_line
+                .set(self.position_of(session.cursor.min(len - 1)));
+            self.clamp_scroll();
+            self.scroll_to_cursor();
+        }
+    }
+
+    fn toggle_follow(&mut self) {
+        if self.file_path.is_none() {
+            self.show_error("follow: no file to follow");
+            return;
+        }
+
+        // Following again if it was paused.
+        self.following = !self.following && self.new_lines.take().is_none();
+        if self.following {
+            if self.loading.is_none() {
+                self.poll_file();
+            }
+            self.scroll_to_bottom();
+        }
+    }
+
+    /// Appends any lines written to the file since the last read. Stops
+    /// following if the file can no longer be read.
+    fn poll_file(&mut self) {
+        if let Err(err) = self.read_appended() {
+            self.following = false;
+            self.new_lines = None;
+            self.show_error(format!("follow: {err}"));
+        }
+    }
+
+    fn read_appended(&mut self) -> io::Result<()> {
+        let Some(path) = &self.file_path else {
+            return Ok(());
+        };
+        let Some(mut file_len) = self.content.borrow().byte_len() else {
+            // Not read from the file directly (it's compressed), so it can't
+            // be resumed part way through.
+            self.start_loading(path.clone(), LoadKind::Refresh);
+            return Ok(());
+        };
+
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        if len < file_len {
+            // Truncated or replaced; start over from the beginning.
+            self.content.borrow_mut().clear();
+            self.bookmarks.clear();
+            self.marks.clear();
+            self.expanded.clear();
+            file_len = 0;
+            self.restyle_from(0);
+            self.extend_visible(0);
+        }
+        if len == file_len {
+            return Ok(());
+        }
+
+        file.seek(SeekFrom::Start(file_len))?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+        let visible_before = self.visible_len();
+        let first_changed = self.content.borrow_mut().append(&bytes);
+        self.run_line_hook(first_changed);
+        self.restyle_from(first_changed);
+        self.extend_visible(first_changed);
+        let appended = self.visible_len().saturating_sub(visible_before);
+        if let Some(new_lines) = &mut self.new_lines {
+            *new_lines += appended;
+        }
+
+        if self.following {
+            self.scroll_to_bottom();
+        }
+        Ok(())
+    }
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+    let mut config = Config::load(args.config.as_deref())?;
+    config.safe |= args.safe;
+    if let Some(name) = &args.filetype
+        && !config.filetypes.contains_key(name)
+    {
+        return Err(format!("unknown filetype '{name}'").into());
+    }
+
+    // Read piped input before the terminal is switched to raw mode so the two
+    // don't contend for stdin; key events are then read from the tty.
+    let piped = if args.files.is_empty() && !io::stdin().is_terminal() {
+        let mut bytes = Vec::new();
+        io::stdin().read_to_end(&mut bytes)?;
+        Some(bytes)
+    } else {
+        None
+    };
+
+    // Ask before opening binary files, which would only show garbage.
+    let had_files = !args.files.is_empty();
+    let mut files = Vec::new();
+    for path in args.files {
+        if io::stdin().is_terminal() && content::looks_binary(&path, args.encoding).unwrap_or(false)
+        {
+            eprint!(
+                "{} looks like a binary file. Open anyway? [y/N] ",
+                path.display()
+            );
+            let mut answer = String::new();
+            io::stdin().read_line(&mut answer)?;
+            if !answer.trim().eq_ignore_ascii_case("y") {
+                continue;
+            }
+        }
+        files.push(path);
+    }
+    if had_files && files.is_empty() {
+        return Ok(());
+    }
+
+    // Put the terminal back before the message of a panic is printed, or
+    // it would be lost on the alternate screen and the shell left raw.
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        let _ = restore_terminal();
+        default_hook(info);
+    }));
+
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(
+        stdout,
+        EnterAlternateScreen,
+        EnableMouseCapture,
+        EnableBracketedPaste
+    )?;
+    let backend = CrosstermBackend::new(stdout);
+    let mut terminal = Terminal::new(backend)?;
+
+    let load_options = content::Options {
+        mmap: args.mmap,
+        encoding: args.encoding,
+        tail: args.tail,
+    };
+    let mut app = App::new(files, piped, load_options, &config)?;
+    app.confirm_quit = !args.no_confirm;
+    app.format = args.format;
+    app.filetype = args.filetype;
+    app.apply_filetype();
+    app.detect_format();
+    // Lines of a tail are numbered from its start, so they don't match a
+    // session of the whole file.
+    app.restore_session = !args.no_restore && args.tail.is_none();
+    if let Some(pattern) = &config.continuation {
+        match RegexBuilder::new(pattern).build() {
+            Ok(regex) => app.continuation = Some(regex),
+            Err(err) => app.show_error(format!("continuation pattern: {err}")),
+        }
+    }
+    match timestamp::Parser::new(config.timestamp_format.as_deref()) {
+        Ok(timestamps) => app.timestamps = timestamps,
+        Err(err) => app.show_error(err),
+    }
+    let (theme, warnings) = theme::Theme::from_config(&config.theme);
+    app.theme = theme;
+    if !warnings.is_empty() {
+        app.show_error(warnings.join("; "));
+    }
+    for (keys, action) in &config.keybindings {
+        if let Err(err) = app.keymap.bind(keys, action) {
+            app.show_error(format!("keybindings: {err}"));
+        }
+    }
+    app.run_init_script(config.init_script.as_deref());
+
+    let res = run_app(&mut terminal, &mut app);
+
+    restore_terminal()?;
+
+    if let Err(err) = res {
+        println!("{err:?}");
+    }
+    if args.tail.is_none()
+        && let Err(err) = app.save_sessions()
+    {
+        eprintln!("logview: saving session: {err}");
+    }
+
+    Ok(())
+}
+
+/// Leaves raw mode and the alternate screen, stops capturing the mouse
+/// and pastes, and shows the cursor.
+fn restore_terminal() -> io::Result<()> {
+    disable_raw_mode()?;
+    execute!(
+        io::stdout(),
+        LeaveAlternateScreen,
+        DisableMouseCapture,
+        DisableBracketedPaste,
+        cursor::Show
+    )
+}
+
+fn run_app<B: ratatui::backend::Backend>(
+    terminal: &mut Terminal<B>,
+    app: &mut App,
+) -> io::Result<()> {
+    // Redraw only when something changed, and at most once a frame.
+    let mut dirty = true;
+    let mut last_draw: Option<Instant> = None;
+    let mut mouse_captured = app.options.mouse;
+    let mut resized = false;
+    loop {
+        let since_draw = last_draw.map_or(FRAME_INTERVAL, |drawn| drawn.elapsed());
+        if dirty && since_draw >= FRAME_INTERVAL {
+            terminal.draw(|f| ui(f, app))?;
+            dirty = false;
+            last_draw = Some(Instant::now());
+        }
+
+        let timeout = if dirty {
+            FRAME_INTERVAL.saturating_sub(since_draw)
+        } else {
+            TICK_INTERVAL
+        };
+        if event::poll(timeout)? {
+            // Handle every queued event before drawing, e.g. a held key.
+            loop {
+                match event::read()? {
+                    Event::Key(key) if key.kind == KeyEventKind::Press => {
+                        app.handle_key_event(key);
+                    }
+                    Event::Mouse(mouse) => app.handle_mouse_event(mouse),
+                    Event::Paste(text) => app.handle_paste(&text),
+                    Event::Resize(..) => resized = true,
+                    _ => {}
+                }
+                dirty = true;
+                if app.should_quit || !event::poll(Duration::ZERO)? {
+                    break;
+                }
+            }
+        }
+        if resized {
+            // Drawing measures the viewport at the new size, which the
+            // current line is then kept within, and drawn again at once.
+            terminal.draw(|f| ui(f, app))?;
+            app.handle_resize();
+            last_draw = None;
+            resized = false;
+        }
+
+        if app.options.mouse != mouse_captured {
+            if app.options.mouse {
+                execute!(io::stdout(), EnableMouseCapture)?;
+            } else {
+                execute!(io::stdout(), DisableMouseCapture)?;
+            }
+            mouse_captured = app.options.mouse;
+        }
+
+        dirty |= app.poll_files();
+        // The other pane of a split keeps up with its file too.
+        if app
+            .split
+            .as_ref()
+            .is_some_and(|split| split.other.buffer != app.active)
+        {
+            app.swap_panes();
+            dirty |= app.poll_files();
+            app.swap_panes();
         }
 
         if app.should_quit {
@@ -185,34 +3577,820 @@ fn run_app<B: ratatui::backend::Backend>(
     }
 }
 
-fn ui(f: &mut ratatui::Frame, app: &App) {
-    let main_area = if app.input_mode == InputMode::Command {
-        let chunks = Layout::default()
-            .direction(Direction::Vertical)
-            .constraints([Constraint::Min(3), Constraint::Length(3)])
-            .split(f.area());
+fn ui(f: &mut ratatui::Frame, app: &mut App) {
+    let prompt = match app.input_mode {
+        InputMode::Command => Some((":", "Command")),
+        InputMode::Search if app.options.ignore_case => Some(("/", "Search (ignore case)")),
+        InputMode::Search => Some(("/", "Search")),
+        InputMode::Normal | InputMode::Visual => None,
+    };
+
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            // A tab bar, once there's more than one tab.
+            Constraint::Length(u16::from(app.buffers.len() > 1)),
+            Constraint::Min(3),
+            Constraint::Length(1),
+            Constraint::Length(if prompt.is_some() { 3 } else { 0 }),
+        ])
+        .split(f.area());
+    let main_area = chunks[1];
+
+    if app.buffers.len() > 1 {
+        let titles: Vec<String> = (0..app.buffers.len())
+            .map(|index| app.tab_title(index))
+            .collect();
+        let tabs = Tabs::new(titles)
+            .select(app.active)
+            .style(Style::default().fg(app.theme.line_number))
+            .highlight_style(
+                Style::default()
+                    .fg(app.theme.border)
+                    .add_modifier(Modifier::BOLD),
+            );
+        f.render_widget(tabs, chunks[0]);
+    }
+
+    if let Some((prefix, title)) = prompt {
+        let area = chunks[3];
+        // Scrolled sideways to keep the cursor in view.
+        let cursor = (prefix.len() + app.input_buffer.cursor_column()) as u16;
+        let width = area.width.saturating_sub(2).max(1);
+        let scroll = cursor.saturating_sub(width - 1);
+        let prompt = Paragraph::new(format!("{prefix}{}", app.input_buffer.as_str()))
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .scroll((0, scroll));
+        f.render_widget(prompt, area);
+        f.set_cursor_position((area.x + 1 + cursor - scroll, area.y + 1));
+    }
+
+    match &app.split {
+        Some(split) => {
+            let areas = Layout::default()
+                .direction(split.direction)
+                .constraints([Constraint::Ratio(1, 2); 2])
+                .split(main_area);
+            let (focused, other) = (areas[split.focused], areas[1 - split.focused]);
+            let same_buffer = split.other.buffer == app.active;
+            // The focused pane is drawn last, so the viewport and clickable
+            // rows are its own.
+            app.swap_panes();
+            render_log(f, app, other, false, false);
+            app.swap_panes();
+            render_log(f, app, focused, true, same_buffer);
+        }
+        None => render_log(f, app, main_area, true, false),
+    }
+    app.update_match_count();
+    render_status_bar(f, app, chunks[2]);
+
+    if app.stats.is_some() {
+        app.update_stats();
+        if let Some(stats) = &app.stats {
+            stats.render(f, f.area(), &app.theme);
+        }
+    }
+    if let Some(histogram) = &app.histogram {
+        histogram.render(f, f.area(), &app.theme);
+    }
+    if let Some(detail) = &mut app.detail {
+        detail.render(f, f.area());
+    }
+    if app.show_help {
+        let mut user_commands: Vec<String> = app.commands.borrow().keys().cloned().collect();
+        user_commands.sort();
+        help::render(
+            f,
+            f.area(),
+            &mut app.help_scroll,
+            &app.keymap,
+            &user_commands,
+        );
+    }
+    if let Some(palette) = &mut app.palette {
+        palette.render(f, f.area());
+    }
+}
+
+/// Draws the lines of the current tab in `area`, as the focused pane or
+/// not. With `keep_cached`, lines cached but not drawn here stay cached for
+/// the other pane.
+fn render_log(f: &mut ratatui::Frame, app: &mut App, area: Rect, focused: bool, keep_cached: bool) {
+    // Inner height of the bordered list, used for page-sized motions.
+    app.viewport_height = area.height.saturating_sub(2) as usize;
+
+    // Wide enough for the largest line number, e.g. 5 columns for 10k lines.
+    let gutter_width = app.content.borrow().len().max(1).ilog10() as usize + 1;
+    let show_numbers = app.options.show_line_numbers || app.options.relative_line_numbers;
+    // A column for bookmark markers, once there are any.
+    let show_marks = !app.bookmarks.is_empty();
+    let gutter_columns = usize::from(show_marks)
+        + if app.options.reltime {
+            RELTIME_WIDTH
+        } else {
+            0
+        }
+        + if show_numbers { gutter_width + 1 } else { 0 };
+    app.viewport_width = (area.width.saturating_sub(2) as usize).saturating_sub(gutter_columns);
+
+    let mut cache = mem::take(&mut app.style_cache);
+    // Lines styled for the other pane of a split of the same tab.
+    let mut styled = if keep_cached {
+        cache.lines.clone()
+    } else {
+        HashMap::new()
+    };
+    let key = app.style_key();
+    if cache.key.as_ref() != Some(&key) {
+        cache.lines.clear();
+        cache.key = Some(key);
+    }
+    let offset = app.scroll_offset.get().min(app.max_scroll_offset());
+    let horizontal_offset = app.horizontal_offset.min(app.max_horizontal_offset());
+    let search = app.active_search();
+    let highlights = app.highlights.borrow();
+    let content = app.content.borrow();
+    let current = app.current_position();
+    let selection = app.visual_selection();
+    // Only the lines that fit are read; each takes at least one row.
+    let bottom = offset
+        .saturating_add(app.viewport_height)
+        .min(app.visible_len());
+    let mut rows_left = app.viewport_height;
+    let mut row_positions = Vec::new();
+    let mut previous_time = if app.options.reltime {
+        app.previous_timestamp(offset)
+    } else {
+        None
+    };
+    let content_lines: Vec<ListItem> = (offset..bottom)
+        .map_while(|position| {
+            if rows_left == 0 {
+                return None;
+            }
+            let index = app.line_at(position);
+            let mut line = cache.lines.remove(&index).unwrap_or_else(|| {
+                let line = content.line(index);
+                let level = if app.options.level_coloring {
+                    line_level(&line, app.is_syslog())
+                } else {
+                    None
+                };
+                let older = app.dim_before.is_some_and(|cutoff| {
+                    app.timestamps
+                        .parse(&line)
+                        .is_some_and(|time| time < cutoff)
+                });
+                let mut line =
+                    style_line(&app.display(line), &highlights, search, level, &app.theme);
+                if older {
+                    for span in &mut line.spans {
+                        span.style = span.style.add_modifier(Modifier::DIM);
+                    }
+                }
+                if app.options.list {
+                    let mut line = show_invisibles(line, app.options.tab_width, &app.theme);
+                    // The line ending, which isn't part of the line.
+                    if content.has_carriage_return(index) {
+                        line.spans.push(Span::styled(
+                            "^M",
+                            Style::default().fg(app.theme.line_number),
+                        ));
+                    }
+                    line
+                } else {
+                    expand_tabs(line, app.options.tab_width)
+                }
+            });
+            styled.insert(index, line.clone());
+            if focused && position == current && app.input_mode != InputMode::Visual {
+                // The first character shown, after the `<` of a scrolled line.
+                let from = match horizontal_offset {
+                    0 => 0,
+                    _ if app.options.wrap => 0,
+                    offset => offset + 1,
+                };
+                line = highlight_brackets(line, from);
+            }
+            if let Some(end) = app.collapsed_entry(index) {
+                line.spans.push(Span::styled(
+                    format!(" [+{} lines]", end - index - 1),
+                    Style::default().fg(app.theme.line_number),
+                ));
+            }
+            let mut rows = if app.options.wrap {
+                wrap_line(line, app.viewport_width, rows_left)
+            } else if horizontal_offset > 0 {
+                vec![skip_columns(line, horizontal_offset, app.theme.line_number)]
+            } else {
+                vec![line]
+            };
+            rows_left = rows_left.saturating_sub(rows.len());
+            // Time since the previous timestamped line; blank without one.
+            let delta = if app.options.reltime {
+                let time = app.timestamp_at(position);
+                let delta = time
+                    .zip(previous_time)
+                    .map(|(time, previous)| time - previous);
+                previous_time = time.or(previous_time);
+                delta
+            } else {
+                None
+            };
+            row_positions.extend(std::iter::repeat_n(position, rows.len()));
+            if gutter_columns > 0 {
+                let mut gutter = Vec::new();
+                if show_marks {
+                    let mark = if app.bookmarks.binary_search(&index).is_ok() {
+                        "*"
+                    } else {
+                        " "
+                    };
+                    gutter.push(Span::styled(mark, Style::default().fg(app.theme.bookmark)));
+                }
+                if show_numbers {
+                    let number = if app.options.relative_line_numbers && position != current {
+                        position.abs_diff(current)
+                    } else {
+                        index + 1
+                    };
+                    gutter.push(Span::styled(
+                        format!("{number:>gutter_width$} "),
+                        Style::default().fg(app.theme.line_number),
+                    ));
+                }
+                if app.options.reltime {
+                    let delta = delta.map_or(String::new(), |delta| {
+                        let sign = if delta >= 0 { "+" } else { "" };
+                        format!("{sign}{}", timestamp::format_duration(delta))
+                    });
+                    gutter.push(Span::styled(
+                        format!("{delta:>width$} ", width = RELTIME_WIDTH - 1),
+                        Style::default().fg(app.theme.line_number),
+                    ));
+                }
+                for (row, line) in rows.iter_mut().enumerate() {
+                    // Continuation rows get a blank gutter.
+                    if row == 0 {
+                        line.spans.splice(0..0, gutter.iter().cloned());
+                    } else {
+                        line.spans.insert(0, Span::raw(" ".repeat(gutter_columns)));
+                    }
+                }
+            }
+            let item = ListItem::new(rows);
+            if !focused {
+                return Some(item);
+            }
+            if app.input_mode == InputMode::Visual {
+                if (selection.0..=selection.1).contains(&position) {
+                    return Some(item.style(Style::default().add_modifier(Modifier::REVERSED)));
+                }
+            } else if position == current {
+                return Some(item.style(Style::default().bg(app.theme.cursor_line)));
+            }
+            Some(item)
+        })
+        .collect();
+    app.style_cache = StyleCache {
+        key: cache.key,
+        lines: styled,
+    };
+    app.log_area = area.inner(Margin {
+        vertical: 1,
+        horizontal: 1,
+    });
+    app.row_positions = row_positions;
+
+    let mut title = "Log View".to_string();
+    if app.split.is_some() {
+        title = format!("{title}: {}", app.tab_title(app.active));
+    }
+    if app.following {
+        title.push_str(" (following)");
+    } else if app.new_lines.is_some() {
+        title.push_str(" (following, paused)");
+    }
+    if app.folding {
+        title.push_str(" (folded)");
+    }
+    let mut block = Block::default()
+        .borders(Borders::ALL)
+        .title(title)
+        .border_style(Style::default().fg(if focused {
+            app.theme.border
+        } else {
+            app.theme.line_number
+        }));
+    if let Some(columns) = &app.columns {
+        block = block
+            .title(Line::from(format!(" columns: {} ", columns.keys().join(", "))).right_aligned());
+    }
+    let has_lua_filter = app.lua_filter.borrow().is_some();
+    if !app.filters.is_empty() || has_lua_filter {
+        let top = match app.filters.last() {
+            Some(filter) => filter.to_string(),
+            None => "<lua>".to_string(),
+        };
+        let summary = format!(
+            " {} filter(s), top: {top} [{}/{} lines] ",
+            app.filters.len() + usize::from(has_lua_filter),
+            app.visible_len(),
+            content.len()
+        );
+        block = block.title_bottom(Line::from(summary).right_aligned());
+    }
+
+    let list = List::new(content_lines).block(block);
+
+    f.render_widget(list, area);
+
+    let visible_len = app.visible_len();
+    if visible_len > app.viewport_height {
+        app.scrollbar_state = app
+            .scrollbar_state
+            .content_length(visible_len.saturating_sub(app.viewport_height) + 1)
+            .viewport_content_length(app.viewport_height)
+            .position(offset);
+        // Drawn over the right border, between the corners.
+        f.render_stateful_widget(
+            Scrollbar::new(ScrollbarOrientation::VerticalRight)
+                .begin_symbol(None)
+                .end_symbol(None),
+            area.inner(Margin {
+                vertical: 1,
+                horizontal: 0,
+            }),
+            &mut app.scrollbar_state,
+        );
+    }
+}
+
+/// Renders the status message, or the file name when there is none, with
+/// the position and input mode on the right.
+fn render_status_bar(f: &mut ratatui::Frame, app: &App, area: ratatui::layout::Rect) {
+    let total = app.content.borrow().len();
+    let visible_len = app.visible_len();
+    let position = app.current_position();
+    let (line, percent) = match ((position + 1) * 100).checked_div(visible_len) {
+        Some(percent) => (app.line_at(position) + 1, percent),
+        None => (0, 100),
+    };
+    let mut position = format!(" {line}/{total} {percent}% {} ", app.input_mode);
+    // Age of the current line.
+    if app.options.reltime
+        && visible_len > 0
+        && let Some(time) = app.timestamp_at(app.current_position())
+    {
+        let age = timestamp::format_duration(timestamp::now_ms() - time);
+        position.insert_str(0, &format!(" {age} ago"));
+    }
+    match app.match_position() {
+        Some((_, 0)) => position.insert_str(0, " [no matches]"),
+        Some((nearest, count)) => {
+            position.insert_str(0, &format!(" [match {nearest} of {count}]"));
+        }
+        None => {}
+    }
+    // Keys typed so far of an unfinished command, like vim's 'showcmd'.
+    if !app.count_buffer.is_empty() || !app.pending_keys.is_empty() {
+        let pending: String = app.pending_keys.iter().map(ToString::to_string).collect();
+        position.insert_str(0, &format!(" {}{pending}", app.count_buffer));
+    }
+    if let Some(new_lines) = app.new_lines.filter(|&new_lines| new_lines > 0) {
+        position.insert_str(0, &format!(" ↓ {new_lines} new"));
+    }
+    if let Some(loading) = &app.loading {
+        position.insert_str(0, &format!(" loading… {}%", loading.loader.percent()));
+    }
+
+    let left = match app.status_message.borrow().as_ref() {
+        Some(message) if message.is_error => {
+            Span::styled(message.text.clone(), Style::default().fg(app.theme.error))
+        }
+        Some(message) => Span::raw(message.text.clone()),
+        None => Span::raw(match &app.file_path {
+            Some(path) => path.display().to_string(),
+            None => "[stdin]".to_string(),
+        }),
+    };
+
+    let chunks = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([
+            Constraint::Min(0),
+            Constraint::Length(position.chars().count() as u16),
+        ])
+        .split(area);
+    f.render_widget(Paragraph::new(Line::from(left)), chunks[0]);
+    f.render_widget(
+        Paragraph::new(position).style(Style::default().add_modifier(Modifier::REVERSED)),
+        chunks[1],
+    );
+}
+
+/// Notes how many lines of `content` had invalid UTF-8 replaced, if any.
+fn utf8_note(content: &Content) -> Option<String> {
+    let invalid = content.invalid_lines();
+    (invalid > 0).then(|| format!("{invalid} line(s) contained invalid UTF-8"))
+}
+
+/// Expands a leading `~` and `$VAR` or `${VAR}` references in a path typed
+/// by the user. Unset variables expand to nothing, as in the shell.
+fn expand_path(path: &str) -> PathBuf {
+    let mut expanded = String::new();
+    let mut rest = path;
+    if let Some(tail) = rest.strip_prefix('~')
+        && (tail.is_empty() || tail.starts_with('/'))
+        && let Some(home) = dirs::home_dir()
+    {
+        expanded.push_str(&home.to_string_lossy());
+        rest = tail;
+    }
 
-        let prompt = Paragraph::new(format!(":{}", app.input_buffer))
-            .block(Block::default().borders(Borders::ALL).title("Command"));
-        f.render_widget(prompt, chunks[1]);
+    while let Some(dollar) = rest.find('$') {
+        expanded.push_str(&rest[..dollar]);
+        let after = &rest[dollar + 1..];
+        let (name, tail) = if let Some(braced) = after.strip_prefix('{')
+            && let Some(end) = braced.find('}')
+        {
+            (&braced[..end], &braced[end + 1..])
+        } else {
+            let end = after
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(after.len());
+            after.split_at(end)
+        };
+        if name.is_empty() {
+            expanded.push('$');
+        } else if let Ok(value) = std::env::var(name) {
+            expanded.push_str(&value);
+        }
+        rest = tail;
+    }
+    expanded.push_str(rest);
+    PathBuf::from(expanded)
+}
 
-        chunks[0]
+/// Completes `prefix` to the paths of the files in its directory whose
+/// names start with what follows the last `/`. Directories get a trailing
+/// `/`, and hidden files are only offered once a `.` is typed.
+fn complete_path(prefix: &str) -> Vec<String> {
+    let (dir, name) = match prefix.rfind('/') {
+        Some(slash) => prefix.split_at(slash + 1),
+        None => ("", prefix),
+    };
+    let search_dir = if dir.is_empty() {
+        PathBuf::from(".")
     } else {
-        f.area()
+        expand_path(dir)
     };
+    let Ok(entries) = fs::read_dir(search_dir) else {
+        return Vec::new();
+    };
+
+    let mut paths: Vec<String> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let file_name = entry.file_name().into_string().ok()?;
+            if !file_name.starts_with(name)
+                || (file_name.starts_with('.') && !name.starts_with('.'))
+            {
+                return None;
+            }
+            let is_dir = entry.path().is_dir();
+            Some(format!("{dir}{file_name}{}", if is_dir { "/" } else { "" }))
+        })
+        .collect();
+    paths.sort();
+    paths
+}
+
+/// The level of `line`, from its severity or message if it's `syslog`.
+fn line_level(line: &str, syslog: bool) -> Option<Level> {
+    match syslog.then(|| syslog::Message::parse(line)).flatten() {
+        Some(message) => message.level(),
+        None => Level::detect(line),
+    }
+}
+
+/// Styles `line` with the color of its `level`, its embedded ANSI colors,
+/// the Lua highlight rules, and finally search matches on top.
+fn style_line(
+    line: &str,
+    highlights: &[script::Highlight],
+    search: Option<&Regex>,
+    level: Option<Level>,
+    theme: &theme::Theme,
+) -> Line<'static> {
+    let (text, runs) = ansi::parse(line);
+    let line = text.as_ref();
+    let mut regions = Vec::new();
+    if let Some(level) = level {
+        regions.push((0, line.len(), level.style(theme)));
+    }
+    regions.extend(runs);
+    for highlight in highlights {
+        let style = Style::default().fg(highlight.color);
+        regions
+            .extend(match_ranges(&highlight.regex, line).map(|(start, end)| (start, end, style)));
+    }
+    if let Some(search) = search {
+        let style = Style::default().fg(theme.match_fg).bg(theme.match_bg);
+        for captures in search.captures_iter(line) {
+            let Some(found) = captures.get(0).filter(|found| found.start() < found.end()) else {
+                continue;
+            };
+            regions.push((found.start(), found.end(), style));
+            // Groups in turn, so nested ones come out on top.
+            for index in 1..captures.len() {
+                if let Some(group) = captures.get(index) {
+                    let color = GROUP_COLORS[(index - 1) % GROUP_COLORS.len()];
+                    regions.push((group.start(), group.end(), style.bg(color)));
+                }
+            }
+        }
+    }
+
+    if regions.is_empty() {
+        return Line::from(line.to_string());
+    }
+    Line::from(split_styled(line, &regions))
+}
+
+/// Replaces the tabs in `line` with spaces up to the next tab stop.
+fn expand_tabs(line: Line<'static>, tab_width: usize) -> Line<'static> {
+    if !line.spans.iter().any(|span| span.content.contains('\t')) {
+        return line;
+    }
+    let mut column = 0;
+    let spans = line
+        .spans
+        .into_iter()
+        .map(|span| {
+            let mut expanded = String::with_capacity(span.content.len());
+            for c in span.content.chars() {
+                if c == '\t' {
+                    let stop = next_tab_stop(column, tab_width);
+                    expanded.extend(std::iter::repeat_n(' ', stop - column));
+                    column = stop;
+                } else {
+                    expanded.push(c);
+                    column += 1;
+                }
+            }
+            Span::styled(expanded, span.style)
+        })
+        .collect::<Vec<_>>();
+    Line::from(spans).style(line.style)
+}
+
+/// Makes the invisible characters of `line` visible for `:set list`: tabs
+/// start with `→` and expand up to the next tab stop, other control
+/// characters are shown in caret notation like `^M`, and trailing spaces
+/// get a background.
+fn show_invisibles(line: Line<'static>, tab_width: usize, theme: &theme::Theme) -> Line<'static> {
+    let total = line
+        .spans
+        .iter()
+        .map(|span| span.content.chars().count())
+        .sum::<usize>();
+    let trailing = line
+        .spans
+        .iter()
+        .rev()
+        .flat_map(|span| span.content.chars().rev())
+        .take_while(|&c| c == ' ')
+        .count();
+    let marker = Style::default().fg(theme.line_number);
+    let trailing_style = Style::default().bg(theme.error);
+
+    let mut spans = Vec::new();
+    let (mut index, mut column) = (0, 0);
+    for span in line.spans {
+        let mut text = String::new();
+        let flush = |text: &mut String, spans: &mut Vec<Span<'static>>| {
+            if !text.is_empty() {
+                spans.push(Span::styled(mem::take(text), span.style));
+            }
+        };
+        for c in span.content.chars() {
+            if c == ' ' && index >= total - trailing {
+                flush(&mut text, &mut spans);
+                spans.push(Span::styled(" ", span.style.patch(trailing_style)));
+                column += 1;
+            } else if c == '\t' {
+                flush(&mut text, &mut spans);
+                let stop = next_tab_stop(column, tab_width);
+                let tab = format!("→{}", " ".repeat(stop - column - 1));
+                spans.push(Span::styled(tab, span.style.patch(marker)));
+                column = stop;
+            } else if let Some(caret) = caret_notation(c) {
+                flush(&mut text, &mut spans);
+                spans.push(Span::styled(caret, span.style.patch(marker)));
+                column += 2;
+            } else {
+                text.push(c);
+                column += 1;
+            }
+            index += 1;
+        }
+        flush(&mut text, &mut spans);
+    }
+    Line::from(spans).style(line.style)
+}
 
-    let content_lines: Vec<ListItem> = app
-        .content
+/// Highlights the first bracket or double quote at or after character
+/// `from` of `line`, as with vim's `%`, together with its partner. Nothing
+/// is highlighted if it has none.
+fn highlight_brackets(line: Line<'static>, from: usize) -> Line<'static> {
+    let chars: Vec<char> = line
+        .spans
         .iter()
-        .map(|line| ListItem::new(Span::styled(line.clone(), Style::default())))
+        .flat_map(|span| span.content.chars())
         .collect();
+    let Some(pair) = (from..chars.len())
+        .find(|&i| "()[]{}\"".contains(chars[i]))
+        .and_then(|i| Some([i, bracket_partner(&chars, i)?]))
+    else {
+        return line;
+    };
 
-    let list = List::new(content_lines).block(
-        Block::default()
-            .borders(Borders::ALL)
-            .title("Log View")
-            .border_style(Style::default().fg(Color::Yellow)),
-    );
+    let highlight = Style::default().add_modifier(Modifier::REVERSED | Modifier::BOLD);
+    let mut spans = Vec::new();
+    let mut index = 0;
+    for span in line.spans {
+        let mut text = String::new();
+        for c in span.content.chars() {
+            if pair.contains(&index) {
+                if !text.is_empty() {
+                    spans.push(Span::styled(mem::take(&mut text), span.style));
+                }
+                spans.push(Span::styled(c.to_string(), span.style.patch(highlight)));
+            } else {
+                text.push(c);
+            }
+            index += 1;
+        }
+        if !text.is_empty() {
+            spans.push(Span::styled(text, span.style));
+        }
+    }
+    Line::from(spans).style(line.style)
+}
+
+/// The index of the bracket matching the one at `chars[at]`, by a balance
+/// scan of the same kind of bracket, or of the quote closing or opening
+/// the string at `at`, skipping escaped quotes.
+fn bracket_partner(chars: &[char], at: usize) -> Option<usize> {
+    let is_quote = |i: usize| {
+        chars[i] == '"' && chars[..i].iter().rev().take_while(|&&c| c == '\\').count() % 2 == 0
+    };
+    if chars[at] == '"' {
+        let opening = (0..at).filter(|&i| is_quote(i)).count() % 2 == 0;
+        return if opening {
+            (at + 1..chars.len()).find(|&i| is_quote(i))
+        } else {
+            (0..at).rev().find(|&i| is_quote(i))
+        };
+    }
+
+    let (open, close, forward) = match chars[at] {
+        '(' => ('(', ')', true),
+        '[' => ('[', ']', true),
+        '{' => ('{', '}', true),
+        ')' => ('(', ')', false),
+        ']' => ('[', ']', false),
+        _ => ('{', '}', false),
+    };
+    let indices: Box<dyn Iterator<Item = usize>> = if forward {
+        Box::new(at..chars.len())
+    } else {
+        Box::new((0..=at).rev())
+    };
+    let mut depth = 0usize;
+    for i in indices {
+        if chars[i] == open {
+            depth = if forward {
+                depth + 1
+            } else {
+                depth.checked_sub(1)?
+            };
+        } else if chars[i] == close {
+            depth = if forward {
+                depth.checked_sub(1)?
+            } else {
+                depth + 1
+            };
+        }
+        if depth == 0 {
+            return Some(i);
+        }
+    }
+    None
+}
+
+/// `c` in caret notation, like `^M` for a carriage return, if it's a
+/// control character other than a tab.
+fn caret_notation(c: char) -> Option<String> {
+    match c {
+        '\t' => None,
+        '\0'..='\x1f' | '\x7f' => Some(format!("^{}", (c as u8 ^ 0x40) as char)),
+        _ => None,
+    }
+}
+
+fn next_tab_stop(column: usize, tab_width: usize) -> usize {
+    (column / tab_width + 1) * tab_width
+}
+
+/// Splits `line` into rows of at most `width` characters, keeping the
+/// styles of the spans that straddle a row boundary. Only the first
+/// `max_rows` rows are made, as no more fit on screen.
+fn wrap_line(line: Line<'static>, width: usize, max_rows: usize) -> Vec<Line<'static>> {
+    if width == 0 {
+        return vec![line];
+    }
+
+    let mut rows = vec![Line::default()];
+    let mut row_width = 0;
+    for span in line.spans {
+        let mut rest = span.content.as_ref();
+        while !rest.is_empty() {
+            if row_width == width {
+                if rows.len() == max_rows {
+                    return rows;
+                }
+                rows.push(Line::default());
+                row_width = 0;
+            }
+            let take = rest
+                .char_indices()
+                .nth(width - row_width)
+                .map_or(rest.len(), |(end, _)| end);
+            let (chunk, tail) = rest.split_at(take);
+            row_width += chunk.chars().count();
+            rows.last_mut()
+                .unwrap()
+                .spans
+                .push(Span::styled(chunk.to_string(), span.style));
+            rest = tail;
+        }
+    }
+    rows
+}
+
+/// Drops the first `columns` characters of `line`, marking the cut with a
+/// `<` in place of the first character still shown.
+fn skip_columns(line: Line<'static>, columns: usize, marker: Color) -> Line<'static> {
+    if line.spans.iter().all(|span| span.content.is_empty()) {
+        return line;
+    }
+
+    let mut skip = columns + 1;
+    let mut spans = vec![Span::styled("<", Style::default().fg(marker))];
+    for span in line.spans {
+        let content = span.content.as_ref();
+        match content.char_indices().nth(skip) {
+            Some((start, _)) => {
+                skip = 0;
+                spans.push(Span::styled(content[start..].to_string(), span.style));
+            }
+            None => skip -= content.chars().count(),
+        }
+    }
+    Line::from(spans)
+}
+
+/// Byte ranges of the non-empty matches of `regex` in `line`.
+fn match_ranges<'a>(regex: &'a Regex, line: &'a str) -> impl Iterator<Item = (usize, usize)> + 'a {
+    regex
+        .find_iter(line)
+        .filter(|found| found.start() < found.end())
+        .map(|found| (found.start(), found.end()))
+}
 
-    f.render_widget(list, main_area);
+/// Splits `line` at every region boundary, patching the styles of all
+/// regions covering a segment in order so later regions win.
+fn split_styled(line: &str, regions: &[(usize, usize, Style)]) -> Vec<Span<'static>> {
+    let mut bounds: Vec<usize> = regions
+        .iter()
+        .flat_map(|&(start, end, _)| [start, end])
+        .chain([0, line.len()])
+        .collect();
+    bounds.sort_unstable();
+    bounds.dedup();
+
+    bounds
+        .windows(2)
+        .map(|segment| {
+            let (start, end) = (segment[0], segment[1]);
+            let style = regions
+                .iter()
+                .filter(|&&(from, to, _)| from <= start && end <= to)
+                .fold(Style::default(), |style, &(_, _, patch)| style.patch(patch));
+            Span::styled(line[start..end].to_string(), style)
+        })
+        .collect()
 }
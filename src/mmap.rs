@@ -0,0 +1,79 @@
+//! Read-only memory maps of files, for `--mmap`.
+//!
+//! Like any file mapping this is only sound as long as the file isn't
+//! truncated while mapped: touching pages past the new end of the file
+//! raises `SIGBUS`.
+
+use std::{fs::File, io, ops::Deref};
+
+pub struct Mmap {
+    ptr: *mut libc::c_void,
+    len: usize,
+}
+
+// SAFETY: the mapping is read-only and owned by this value alone, so
+// nothing ties it to the thread that created it.
+unsafe impl Send for Mmap {}
+
+impl Mmap {
+    /// Maps the whole of `file` as it is now.
+    ///
+    /// # Safety
+    ///
+    /// The file mustn't be truncated while the map is alive, or reading it
+    /// raises `SIGBUS`. Bytes changed by other writers show through, so
+    /// they mustn't be relied on to stay the same either.
+    pub unsafe fn map(file: &File) -> io::Result<Mmap> {
+        let len = usize::try_from(file.metadata()?.len())
+            .map_err(|_| io::Error::other("file too large to map"))?;
+        if len == 0 {
+            // mmap rejects empty mappings.
+            return Ok(Mmap {
+                ptr: std::ptr::null_mut(),
+                len,
+            });
+        }
+
+        // SAFETY: a fresh read-only mapping of a valid descriptor, which
+        // doesn't alias any Rust memory; failure is checked below.
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                libc::PROT_READ,
+                libc::MAP_SHARED,
+                std::os::fd::AsRawFd::as_raw_fd(file),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Mmap { ptr, len })
+    }
+}
+
+impl Deref for Mmap {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        if self.len == 0 {
+            return &[];
+        }
+        // SAFETY: `ptr` is a live mapping of `len` readable bytes, which
+        // `map`'s caller keeps backed by the file.
+        unsafe { std::slice::from_raw_parts(self.ptr as *const u8, self.len) }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        if self.len != 0 {
+            // SAFETY: `ptr` and `len` are exactly what `mmap` returned, and
+            // no slices of the mapping outlive `self`.
+            unsafe {
+                libc::munmap(self.ptr, self.len);
+            }
+        }
+    }
+}
@@ -0,0 +1,156 @@
+//! Settings changed at runtime with `:set`, as in vim: `:set wrap` and
+//! `:set nowrap`, `:set wrap!` to toggle, `:set tabstop=4`, and
+//! `:set wrap?` to show one.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    /// Match searches regardless of case.
+    pub ignore_case: bool,
+    /// Let `n` and `N` wrap around the ends of the file.
+    pub wrapscan: bool,
+    pub show_line_numbers: bool,
+    /// Number lines relative to the current line, which keeps its absolute
+    /// number.
+    pub relative_line_numbers: bool,
+    /// Color whole lines by their detected log level.
+    pub level_coloring: bool,
+    /// Columns between tab stops.
+    pub tab_width: usize,
+    /// Show the time since the previous timestamped line in the gutter.
+    pub reltime: bool,
+    /// Show JSON object lines as a summary of their fields.
+    pub json: bool,
+    /// Wrap long lines over several rows instead of clipping them.
+    pub wrap: bool,
+    /// Capture the mouse for wheel scrolling. Off, the terminal's own text
+    /// selection works instead.
+    pub mouse: bool,
+    /// Scroll the other pane of a split along with the focused one.
+    pub scrollbind: bool,
+    /// Show tabs, control characters and trailing spaces.
+    pub list: bool,
+}
+
+enum Field {
+    Flag(fn(&mut Options) -> &mut bool),
+    /// A number and the range it must be in.
+    Number(fn(&mut Options) -> &mut usize, usize, usize),
+}
+
+/// Every option, by its names, full name first, in the order `:set` lists
+/// them.
+const OPTIONS: &[(&[&str], Field)] = &[
+    (
+        &["ignorecase", "ic"],
+        Field::Flag(|options| &mut options.ignore_case),
+    ),
+    (
+        &["wrapscan", "ws"],
+        Field::Flag(|options| &mut options.wrapscan),
+    ),
+    (
+        &["number", "nu"],
+        Field::Flag(|options| &mut options.show_line_numbers),
+    ),
+    (
+        &["relativenumber", "rnu"],
+        Field::Flag(|options| &mut options.relative_line_numbers),
+    ),
+    (
+        &["levelcolor"],
+        Field::Flag(|options| &mut options.level_coloring),
+    ),
+    (
+        &["tabstop", "ts"],
+        Field::Number(|options| &mut options.tab_width, 1, 32),
+    ),
+    (&["reltime"], Field::Flag(|options| &mut options.reltime)),
+    (&["json"], Field::Flag(|options| &mut options.json)),
+    (&["wrap"], Field::Flag(|options| &mut options.wrap)),
+    (&["mouse"], Field::Flag(|options| &mut options.mouse)),
+    (
+        &["scrollbind", "scb"],
+        Field::Flag(|options| &mut options.scrollbind),
+    ),
+    (&["list"], Field::Flag(|options| &mut options.list)),
+];
+
+fn find(name: &str) -> Option<&'static (&'static [&'static str], Field)> {
+    OPTIONS.iter().find(|(names, _)| names.contains(&name))
+}
+
+/// Full names of the options, for completion.
+pub fn names() -> impl Iterator<Item = &'static str> {
+    OPTIONS.iter().map(|(names, _)| names[0])
+}
+
+impl Options {
+    /// Applies one `:set` argument. Returns the value to show for queries,
+    /// like `wrap?` or a bare `tabstop`.
+    pub fn set(&mut self, arg: &str) -> Result<Option<String>, String> {
+        let unknown = || format!("set: unknown option '{arg}'");
+        if let Some((name, value)) = arg.split_once('=') {
+            return match find(name).ok_or_else(unknown)? {
+                (names, Field::Number(field, min, max)) => {
+                    let number = value
+                        .parse::<usize>()
+                        .map_err(|_| format!("set: invalid number '{value}' for {}", names[0]))?;
+                    if !(*min..=*max).contains(&number) {
+                        return Err(format!("set: {} must be between {min} and {max}", names[0]));
+                    }
+                    *field(self) = number;
+                    Ok(None)
+                }
+                (names, Field::Flag(_)) => Err(format!("set: {} doesn't take a value", names[0])),
+            };
+        }
+        if let Some(name) = arg.strip_suffix('?') {
+            let (names, field) = find(name).ok_or_else(unknown)?;
+            return Ok(Some(self.describe(names[0], field)));
+        }
+        if let Some(name) = arg.strip_suffix('!') {
+            return match find(name).ok_or_else(unknown)? {
+                (_, Field::Flag(field)) => {
+                    let flag = field(self);
+                    *flag = !*flag;
+                    Ok(None)
+                }
+                (names, Field::Number(..)) => Err(format!("set: {} isn't on or off", names[0])),
+            };
+        }
+        match find(arg) {
+            Some((_, Field::Flag(field))) => {
+                *field(self) = true;
+                Ok(None)
+            }
+            // A bare number option shows its value, as in vim.
+            Some((names, field)) => Ok(Some(self.describe(names[0], field))),
+            None => match arg.strip_prefix("no").and_then(find) {
+                Some((_, Field::Flag(field))) => {
+                    *field(self) = false;
+                    Ok(None)
+                }
+                _ => Err(unknown()),
+            },
+        }
+    }
+
+    /// Every option and its value, by their short names to fit the status
+    /// bar, as `:set` with no argument shows them.
+    pub fn describe_all(&self) -> Vec<String> {
+        OPTIONS
+            .iter()
+            .map(|(names, field)| self.describe(names[names.len() - 1], field))
+            .collect()
+    }
+
+    fn describe(&self, name: &str, field: &Field) -> String {
+        // Fields are reached through `&mut`, so read them from a copy.
+        let mut options = self.clone();
+        match field {
+            Field::Flag(field) if *field(&mut options) => name.to_string(),
+            Field::Flag(_) => format!("no{name}"),
+            Field::Number(field, ..) => format!("{name}={}", field(&mut options)),
+        }
+    }
+}
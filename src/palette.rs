@@ -0,0 +1,193 @@
+//! The command palette opened with Ctrl-p, listing the commands and the
+//! files viewed recently, narrowed down by a fuzzy match on what's typed.
+
+use crate::input::Input;
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph},
+};
+
+/// Rows of the list shown at once.
+const LIST_HEIGHT: u16 = 12;
+
+pub struct Item {
+    /// What's matched: the command's name or the file's path.
+    name: String,
+    /// What's shown.
+    label: String,
+    description: String,
+    /// The command line it stands for.
+    pub command: String,
+    /// Whether the command needs arguments, so it's put at the `:` prompt
+    /// to finish instead of being run.
+    pub needs_argument: bool,
+}
+
+impl Item {
+    pub fn command(name: &str, args: &str, description: &str) -> Item {
+        Item {
+            name: name.to_string(),
+            label: format!("{name} {args}").trim_end().to_string(),
+            description: description.to_string(),
+            command: name.to_string(),
+            // Optional arguments are in brackets.
+            needs_argument: !args.is_empty() && !args.starts_with('['),
+        }
+    }
+
+    pub fn file(path: &str) -> Item {
+        Item {
+            name: path.to_string(),
+            label: path.to_string(),
+            description: "recent file".to_string(),
+            command: format!("open {path}"),
+            needs_argument: false,
+        }
+    }
+}
+
+pub struct Palette {
+    pub input: Input,
+    items: Vec<Item>,
+    /// Indices of the items matching the input, best first.
+    matches: Vec<usize>,
+    list: ListState,
+}
+
+impl Palette {
+    pub fn new(items: Vec<Item>) -> Palette {
+        let mut palette = Palette {
+            input: Input::default(),
+            matches: Vec::new(),
+            items,
+            list: ListState::default(),
+        };
+        palette.filter();
+        palette
+    }
+
+    /// Matches the items against the input again, after it changed.
+    pub fn filter(&mut self) {
+        let query = self.input.as_str();
+        let mut scored: Vec<(i64, usize)> = self
+            .items
+            .iter()
+            .enumerate()
+            .filter_map(|(index, item)| fuzzy_score(query, &item.name).map(|score| (score, index)))
+            .collect();
+        // Stable, so equal scores keep the items' order.
+        scored.sort_by_key(|&(score, _)| std::cmp::Reverse(score));
+        self.matches = scored.into_iter().map(|(_, index)| index).collect();
+        self.list.select((!self.matches.is_empty()).then_some(0));
+    }
+
+    /// Moves the selection down, or up if `forward` is false, wrapping
+    /// around the ends.
+    pub fn select_next(&mut self, forward: bool) {
+        let len = self.matches.len();
+        if let Some(selected) = self.list.selected()
+            && len > 0
+        {
+            self.list.select(Some(if forward {
+                (selected + 1) % len
+            } else {
+                (selected + len - 1) % len
+            }));
+        }
+    }
+
+    pub fn selected(&self) -> Option<&Item> {
+        let index = self.matches.get(self.list.selected()?)?;
+        Some(&self.items[*index])
+    }
+
+    /// Draws the palette near the top of `area`, with the cursor in its
+    /// input.
+    pub fn render(&mut self, f: &mut Frame, area: Rect) {
+        let width = 72.min(area.width);
+        let height = (LIST_HEIGHT + 4).min(area.height);
+        let popup = Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + (area.height - height) / 4,
+            width,
+            height,
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title("Palette")
+            .title_bottom(
+                Line::from(" ↑ ↓ to select, Enter to run, Esc to close ").right_aligned(),
+            );
+        let inner = block.inner(popup);
+        f.render_widget(Clear, popup);
+        f.render_widget(block, popup);
+        if inner.height < 2 || inner.width == 0 {
+            return;
+        }
+
+        let input_area = Rect { height: 1, ..inner };
+        let prompt = format!("> {}", self.input.as_str());
+        f.render_widget(Paragraph::new(prompt), input_area);
+        let cursor = (2 + self.input.cursor_column()) as u16;
+        f.set_cursor_position((input_area.x + cursor.min(inner.width - 1), input_area.y));
+
+        let label_width = self
+            .matches
+            .iter()
+            .map(|&index| self.items[index].label.chars().count())
+            .max()
+            .unwrap_or(0)
+            .min(inner.width as usize / 2);
+        let items: Vec<ListItem> = self
+            .matches
+            .iter()
+            .map(|&index| {
+                let item = &self.items[index];
+                ListItem::new(Line::from(vec![
+                    Span::styled(
+                        format!(" {:<label_width$}  ", item.label),
+                        Style::default().fg(Color::Yellow),
+                    ),
+                    Span::raw(item.description.clone()),
+                ]))
+            })
+            .collect();
+        let list =
+            List::new(items).highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        let list_area = Rect {
+            y: inner.y + 2,
+            height: inner.height - 2,
+            ..inner
+        };
+        f.render_stateful_widget(list, list_area, &mut self.list);
+    }
+}
+
+/// How well `query` matches `text` with its characters in order but not
+/// necessarily together, ignoring case, or `None` if it doesn't. Runs of
+/// adjacent characters and matches at the start of words score higher.
+fn fuzzy_score(query: &str, text: &str) -> Option<i64> {
+    let text: Vec<char> = text.chars().collect();
+    let mut score = 0;
+    let mut next = 0;
+    let mut previous: Option<usize> = None;
+    for wanted in query.chars().filter(|c| !c.is_whitespace()) {
+        let found =
+            (next..text.len()).find(|&i| text[i].to_lowercase().eq(wanted.to_lowercase()))?;
+        score += 1;
+        if previous.is_some_and(|previous| previous + 1 == found) {
+            score += 5;
+        }
+        if found == 0 || !text[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        // Characters skipped cost a little, so tighter matches win.
+        score -= (found - next) as i64 / 4;
+        previous = Some(found);
+        next = found + 1;
+    }
+    Some(score)
+}
@@ -0,0 +1,1007 @@
+//! A small regular expression engine covering the syntax logview needs for
+//! searching and filtering. The API mirrors the subset of the `regex` crate
+//! used by the app.
+//!
+//! Patterns are parsed into an AST, compiled into a program and executed with
+//! a Pike VM, so matching time is linear in the length of the input and
+//! leftmost-first semantics match the `regex` crate.
+//!
+//! Supported syntax: literals, `.`, `^`, `$`, `\b`, `\B`, character classes
+//! (`[a-z]`, `[^0-9]`, `\d`, `\w`, `\s` and their negations), groups
+//! (`(...)`, `(?:...)`), alternation, the `*`, `+`, `?` and `{n,m}`
+//! quantifiers with lazy variants, and a leading `(?i)` flag.
+
+use std::fmt;
+
+/// An error encountered while parsing a pattern.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    message: String,
+    position: usize,
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "regex parse error at {}: {}",
+            self.position, self.message
+        )
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A compiled regular expression.
+#[derive(Debug, Clone)]
+pub struct Regex {
+    pattern: String,
+    case_insensitive: bool,
+    program: Program,
+}
+
+impl Regex {
+    pub fn as_str(&self) -> &str {
+        &self.pattern
+    }
+
+    /// Whether matching ignores case, from the builder or a `(?i)` flag.
+    pub fn is_case_insensitive(&self) -> bool {
+        self.case_insensitive
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        self.program.exec(text, 0, 0).is_some()
+    }
+
+    /// Iterates over successive non-overlapping matches in `text`.
+    pub fn find_iter<'r, 't>(&'r self, text: &'t str) -> Matches<'r, 't> {
+        // Only the slots of the whole match are tracked.
+        Matches(CaptureMatches {
+            regex: self,
+            text,
+            position: 0,
+            slots: 2,
+        })
+    }
+
+    /// Iterates over the capture groups of successive non-overlapping
+    /// matches in `text`.
+    pub fn captures_iter<'r, 't>(&'r self, text: &'t str) -> CaptureMatches<'r, 't> {
+        CaptureMatches {
+            regex: self,
+            text,
+            position: 0,
+            slots: self.program.slots,
+        }
+    }
+}
+
+/// Escapes the characters of `text` that have a meaning in patterns, so it
+/// matches literally.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if "\\.+*?()|[]{}^$-".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Configures and compiles a [`Regex`].
+#[derive(Debug, Clone)]
+pub struct RegexBuilder {
+    pattern: String,
+    case_insensitive: bool,
+}
+
+impl RegexBuilder {
+    pub fn new(pattern: &str) -> RegexBuilder {
+        RegexBuilder {
+            pattern: pattern.to_string(),
+            case_insensitive: false,
+        }
+    }
+
+    pub fn case_insensitive(&mut self, yes: bool) -> &mut RegexBuilder {
+        self.case_insensitive = yes;
+        self
+    }
+
+    pub fn build(&self) -> Result<Regex, Error> {
+        let mut parser = Parser::new(&self.pattern);
+        let ast = parser.parse()?;
+        if program_size(&ast) > MAX_PROGRAM_SIZE {
+            return Err(Error {
+                message: "pattern too large".to_string(),
+                position: 0,
+            });
+        }
+        let case_insensitive = self.case_insensitive || parser.case_insensitive;
+        Ok(Regex {
+            pattern: self.pattern.clone(),
+            case_insensitive,
+            program: Program::compile(&ast, parser.groups, case_insensitive),
+        })
+    }
+}
+
+/// The byte range of a single match of a regex in a haystack.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Match {
+    start: usize,
+    end: usize,
+}
+
+impl Match {
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    pub fn end(&self) -> usize {
+        self.end
+    }
+}
+
+/// The groups of a match: the whole match as group 0, then each
+/// parenthesized group in the order it opens.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Captures {
+    slots: Vec<Option<usize>>,
+}
+
+impl Captures {
+    /// Group `index`, or `None` if there is no such group or it didn't
+    /// take part in the match.
+    pub fn get(&self, index: usize) -> Option<Match> {
+        let start = (*self.slots.get(index * 2)?)?;
+        let end = (*self.slots.get(index * 2 + 1)?)?;
+        Some(Match { start, end })
+    }
+
+    /// Number of groups, including the whole match.
+    pub fn len(&self) -> usize {
+        self.slots.len() / 2
+    }
+}
+
+/// Iterator returned by [`Regex::captures_iter`].
+pub struct CaptureMatches<'r, 't> {
+    regex: &'r Regex,
+    text: &'t str,
+    position: usize,
+    /// Capture slots tracked: two per group, from the whole match.
+    slots: usize,
+}
+
+impl Iterator for CaptureMatches<'_, '_> {
+    type Item = Captures;
+
+    fn next(&mut self) -> Option<Captures> {
+        if self.position > self.text.len() {
+            return None;
+        }
+
+        let slots = self
+            .regex
+            .program
+            .exec(self.text, self.position, self.slots)?;
+        let (start, end) = (slots[0]?, slots[1]?);
+        self.position = if end == start {
+            // Step past empty matches so iteration always makes progress.
+            end + self.text[end..].chars().next().map_or(1, char::len_utf8)
+        } else {
+            end
+        };
+
+        Some(Captures { slots })
+    }
+}
+
+/// Iterator returned by [`Regex::find_iter`].
+pub struct Matches<'r, 't>(CaptureMatches<'r, 't>);
+
+impl Iterator for Matches<'_, '_> {
+    type Item = Match;
+
+    fn next(&mut self) -> Option<Match> {
+        self.0.next()?.get(0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum ClassItem {
+    Range(char, char),
+    Digit(bool),
+    Word(bool),
+    Space(bool),
+}
+
+impl ClassItem {
+    fn matches(&self, c: char) -> bool {
+        match *self {
+            ClassItem::Range(lo, hi) => lo <= c && c <= hi,
+            ClassItem::Digit(negated) => c.is_ascii_digit() != negated,
+            ClassItem::Word(negated) => is_word_char(c) != negated,
+            ClassItem::Space(negated) => c.is_whitespace() != negated,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Class {
+    items: Vec<ClassItem>,
+    negated: bool,
+}
+
+impl Class {
+    fn single(item: ClassItem) -> Class {
+        Class {
+            items: vec![item],
+            negated: false,
+        }
+    }
+
+    fn matches(&self, c: char, case_insensitive: bool) -> bool {
+        let hit = |c: char| self.items.iter().any(|item| item.matches(c));
+        let found = hit(c)
+            || (case_insensitive && (c.to_lowercase().any(hit) || c.to_uppercase().any(hit)));
+        found != self.negated
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Assertion {
+    Start,
+    End,
+    WordBoundary,
+    NotWordBoundary,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Assert(Assertion),
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alternate(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    },
+}
+
+/// Upper bound on counted repetitions, keeping compiled programs small.
+const MAX_REPEAT: usize = 1000;
+
+/// Upper bound on the instructions of a compiled program. Nested counted
+/// repetitions multiply, so each count being small isn't enough; matching
+/// takes time in proportion to the size for every character.
+const MAX_PROGRAM_SIZE: usize = 20_000;
+
+/// Instructions `node` compiles to, as `Program::emit` lays them out,
+/// saturating rather than overflowing for absurd nestings.
+fn program_size(node: &Node) -> usize {
+    match node {
+        Node::Empty => 0,
+        Node::Char(_) | Node::Any | Node::Class(_) | Node::Assert(_) => 1,
+        Node::Group(node, index) => {
+            program_size(node).saturating_add(if index.is_some() { 2 } else { 0 })
+        }
+        Node::Concat(nodes) => nodes
+            .iter()
+            .map(program_size)
+            .fold(0, usize::saturating_add),
+        Node::Alternate(branches) => branches
+            .iter()
+            .map(program_size)
+            .fold(2 * (branches.len() - 1), usize::saturating_add),
+        Node::Repeat { node, min, max, .. } => {
+            let size = program_size(node);
+            let optional = match max {
+                None => size.saturating_add(2),
+                Some(max) => (max - min).saturating_mul(size.saturating_add(1)),
+            };
+            min.saturating_mul(size).saturating_add(optional)
+        }
+    }
+}
+
+struct Parser<'p> {
+    chars: Vec<char>,
+    position: usize,
+    groups: usize,
+    case_insensitive: bool,
+    pattern: &'p str,
+}
+
+impl<'p> Parser<'p> {
+    fn new(pattern: &'p str) -> Parser<'p> {
+        Parser {
+            chars: pattern.chars().collect(),
+            position: 0,
+            groups: 0,
+            case_insensitive: false,
+            pattern,
+        }
+    }
+
+    fn error(&self, message: &str) -> Error {
+        Error {
+            message: message.to_string(),
+            position: self.position,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse(&mut self) -> Result<Node, Error> {
+        if self.pattern.starts_with("(?i)") {
+            self.case_insensitive = true;
+            self.position = 4;
+        }
+
+        let node = self.parse_alternation()?;
+        if self.position < self.chars.len() {
+            return Err(self.error("unmatched ')'"));
+        }
+        Ok(node)
+    }
+
+    fn parse_alternation(&mut self) -> Result<Node, Error> {
+        let mut branches = vec![self.parse_concat()?];
+        while self.eat('|') {
+            branches.push(self.parse_concat()?);
+        }
+
+        Ok(if branches.len() == 1 {
+            branches.pop().unwrap()
+        } else {
+            Node::Alternate(branches)
+        })
+    }
+
+    fn parse_concat(&mut self) -> Result<Node, Error> {
+        let mut nodes = Vec::new();
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let atom = self.parse_atom()?;
+            nodes.push(self.parse_quantifiers(atom)?);
+        }
+
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap(),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn parse_quantifiers(&mut self, mut node: Node) -> Result<Node, Error> {
+        loop {
+            let (min, max) = match self.peek() {
+                Some('*') => (0, None),
+                Some('+') => (1, None),
+                Some('?') => (0, Some(1)),
+                Some('{') => match self.parse_counted()? {
+                    Some(bounds) => bounds,
+                    None => return Ok(node),
+                },
+                _ => return Ok(node),
+            };
+            // Step past the operator, or the closing brace of a count.
+            self.position += 1;
+
+            if matches!(node, Node::Empty | Node::Assert(_)) {
+                return Err(self.error("nothing to repeat"));
+            }
+            let greedy = !self.eat('?');
+            node = Node::Repeat {
+                node: Box::new(node),
+                min,
+                max,
+                greedy,
+            };
+        }
+    }
+
+    /// Parses `{n}`, `{n,}` or `{n,m}`, leaving the cursor on the closing
+    /// brace. A `{` not followed by a number is a literal, as in JSON.
+    fn parse_counted(&mut self) -> Result<Option<(usize, Option<usize>)>, Error> {
+        let start = self.position;
+        self.position += 1;
+
+        let Some(min) = self.parse_number() else {
+            self.position = start;
+            return Ok(None);
+        };
+        let max = if self.eat(',') {
+            self.parse_number()
+        } else {
+            Some(min)
+        };
+        if self.peek() != Some('}') {
+            return Err(self.error("unclosed counted repetition"));
+        }
+        if max.is_some_and(|max| max < min) {
+            return Err(self.error("invalid repetition range"));
+        }
+        if min > MAX_REPEAT || max.is_some_and(|max| max > MAX_REPEAT) {
+            return Err(self.error("repetition count too large"));
+        }
+        Ok(Some((min, max)))
+    }
+
+    fn parse_number(&mut self) -> Option<usize> {
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.position += 1;
+        }
+        self.chars[start..self.position]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .ok()
+    }
+
+    fn parse_atom(&mut self) -> Result<Node, Error> {
+        let c = self.peek().ok_or_else(|| self.error("unexpected end"))?;
+        self.position += 1;
+
+        match c {
+            '(' => self.parse_group(),
+            '[' => self.parse_class().map(Node::Class),
+            '.' => Ok(Node::Any),
+            '^' => Ok(Node::Assert(Assertion::Start)),
+            '$' => Ok(Node::Assert(Assertion::End)),
+            '\\' => self.parse_escape(),
+            '*' | '+' | '?' => Err(self.error("nothing to repeat")),
+            c => Ok(Node::Char(c)),
+        }
+    }
+
+    fn parse_group(&mut self) -> Result<Node, Error> {
+        let index = if self.eat('?') {
+            if !self.eat(':') {
+                return Err(self.error("unsupported group flag"));
+            }
+            None
+        } else {
+            self.groups += 1;
+            Some(self.groups)
+        };
+
+        let node = self.parse_alternation()?;
+        if !self.eat(')') {
+            return Err(self.error("unclosed group"));
+        }
+        Ok(Node::Group(Box::new(node), index))
+    }
+
+    fn parse_escape(&mut self) -> Result<Node, Error> {
+        let c = self.peek().ok_or_else(|| self.error("incomplete escape"))?;
+        self.position += 1;
+
+        Ok(match c {
+            'b' => Node::Assert(Assertion::WordBoundary),
+            'B' => Node::Assert(Assertion::NotWordBoundary),
+            _ => match self.class_escape(c)? {
+                ClassItem::Range(c, _) => Node::Char(c),
+                item => Node::Class(Class::single(item)),
+            },
+        })
+    }
+
+    /// Interprets the character after a backslash, both inside and outside
+    /// of a bracketed class.
+    fn class_escape(&self, c: char) -> Result<ClassItem, Error> {
+        Ok(match c {
+            'd' => ClassItem::Digit(false),
+            'D' => ClassItem::Digit(true),
+            'w' => ClassItem::Word(false),
+            'W' => ClassItem::Word(true),
+            's' => ClassItem::Space(false),
+            'S' => ClassItem::Space(true),
+            't' => ClassItem::Range('\t', '\t'),
+            'n' => ClassItem::Range('\n', '\n'),
+            'r' => ClassItem::Range('\r', '\r'),
+            c if c.is_ascii_alphanumeric() => {
+                return Err(self.error(&format!("unrecognized escape '\\{c}'")));
+            }
+            c => ClassItem::Range(c, c),
+        })
+    }
+
+    fn parse_class(&mut self) -> Result<Class, Error> {
+        let negated = self.eat('^');
+        let mut items = Vec::new();
+        let mut first = true;
+
+        loop {
+            let c = self
+                .peek()
+                .ok_or_else(|| self.error("unclosed character class"))?;
+            self.position += 1;
+            if c == ']' && !first {
+                break;
+            }
+            first = false;
+
+            let item = if c == '\\' {
+                let escaped = self
+                    .peek()
+                    .ok_or_else(|| self.error("unclosed character class"))?;
+                self.position += 1;
+                self.class_escape(escaped)?
+            } else {
+                ClassItem::Range(c, c)
+            };
+
+            // A '-' between two single characters forms a range.
+            if let ClassItem::Range(lo, _) = item
+                && self.peek() == Some('-')
+                && self.chars.get(self.position + 1).is_some_and(|&c| c != ']')
+            {
+                self.position += 1;
+                let mut hi = self.chars[self.position];
+                self.position += 1;
+                if hi == '\\' {
+                    let escaped = self
+                        .peek()
+                        .ok_or_else(|| self.error("unclosed character class"))?;
+                    self.position += 1;
+                    match self.class_escape(escaped)? {
+                        ClassItem::Range(c, _) => hi = c,
+                        _ => return Err(self.error("invalid range in character class")),
+                    }
+                }
+                if hi < lo {
+                    return Err(self.error("invalid range in character class"));
+                }
+                items.push(ClassItem::Range(lo, hi));
+            } else {
+                items.push(item);
+            }
+        }
+
+        Ok(Class { items, negated })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Assert(Assertion),
+    /// Try both targets, preferring the first.
+    Split(usize, usize),
+    Jump(usize),
+    Save(usize),
+    Match,
+}
+
+#[derive(Debug, Clone)]
+struct Program {
+    insts: Vec<Inst>,
+    slots: usize,
+    case_insensitive: bool,
+}
+
+impl Program {
+    fn compile(ast: &Node, groups: usize, case_insensitive: bool) -> Program {
+        let mut program = Program {
+            insts: vec![Inst::Save(0)],
+            slots: (groups + 1) * 2,
+            case_insensitive,
+        };
+        program.emit(ast);
+        program.insts.push(Inst::Save(1));
+        program.insts.push(Inst::Match);
+        program
+    }
+
+    fn emit(&mut self, node: &Node) {
+        match node {
+            Node::Empty => {}
+            Node::Char(c) => self.insts.push(Inst::Char(*c)),
+            Node::Any => self.insts.push(Inst::Any),
+            Node::Class(class) => self.insts.push(Inst::Class(class.clone())),
+            Node::Assert(assertion) => self.insts.push(Inst::Assert(*assertion)),
+            Node::Group(node, index) => match index {
+                Some(index) => {
+                    self.insts.push(Inst::Save(index * 2));
+                    self.emit(node);
+                    self.insts.push(Inst::Save(index * 2 + 1));
+                }
+                None => self.emit(node),
+            },
+            Node::Concat(nodes) => nodes.iter().for_each(|node| self.emit(node)),
+            Node::Alternate(branches) => {
+                let mut jumps = Vec::new();
+                for (i, branch) in branches.iter().enumerate() {
+                    if i + 1 < branches.len() {
+                        let split = self.insts.len();
+                        self.insts.push(Inst::Split(split + 1, 0));
+                        self.emit(branch);
+                        jumps.push(self.insts.len());
+                        self.insts.push(Inst::Jump(0));
+                        let next = self.insts.len();
+                        self.insts[split] = Inst::Split(split + 1, next);
+                    } else {
+                        self.emit(branch);
+                    }
+                }
+                let end = self.insts.len();
+                for jump in jumps {
+                    self.insts[jump] = Inst::Jump(end);
+                }
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                for _ in 0..*min {
+                    self.emit(node);
+                }
+                match max {
+                    None => {
+                        let split = self.insts.len();
+                        self.insts.push(Inst::Split(0, 0));
+                        self.emit(node);
+                        self.insts.push(Inst::Jump(split));
+                        let end = self.insts.len();
+                        self.insts[split] = self.split(split + 1, end, *greedy);
+                    }
+                    Some(max) => {
+                        let mut splits = Vec::new();
+                        for _ in *min..*max {
+                            splits.push(self.insts.len());
+                            self.insts.push(Inst::Split(0, 0));
+                            self.emit(node);
+                        }
+                        let end = self.insts.len();
+                        for split in splits {
+                            self.insts[split] = self.split(split + 1, end, *greedy);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    fn split(&self, body: usize, exit: usize, greedy: bool) -> Inst {
+        if greedy {
+            Inst::Split(body, exit)
+        } else {
+            Inst::Split(exit, body)
+        }
+    }
+
+    fn char_matches(&self, expected: char, c: char) -> bool {
+        expected == c
+            || (self.case_insensitive
+                && (expected.to_lowercase().eq(c.to_lowercase())
+                    || expected.to_uppercase().eq(c.to_uppercase())))
+    }
+
+    /// Runs the program over `text` starting at byte offset `start` and
+    /// returns the first `slots` capture slots of the leftmost-first match.
+    /// With none, it returns as soon as anything matches, and threads carry
+    /// no captures to copy.
+    fn exec(&self, text: &str, start: usize, slots: usize) -> Option<Vec<Option<usize>>> {
+        let slot_count = slots.min(self.slots);
+        let mut current = Threads::new(self.insts.len());
+        let mut next = Threads::new(self.insts.len());
+        let mut matched = None;
+
+        let mut position = start;
+        let mut prev = text[..start].chars().next_back();
+        loop {
+            let c = text[position..].chars().next();
+
+            if matched.is_none() {
+                let slots = vec![None; slot_count];
+                self.add_thread(&mut current, 0, slots, position, prev, c);
+            }
+            if current.is_empty() && matched.is_some() {
+                break;
+            }
+
+            for i in 0..current.len() {
+                let (pc, slots) = current.take(i);
+                let advance = match (&self.insts[pc], c) {
+                    (Inst::Char(expected), Some(c)) => self.char_matches(*expected, c),
+                    (Inst::Any, Some(c)) => c != '\n',
+                    (Inst::Class(class), Some(c)) => class.matches(c, self.case_insensitive),
+                    (Inst::Match, _) if slot_count == 0 => return Some(slots),
+                    (Inst::Match, _) => {
+                        matched = Some(slots);
+                        // Lower-priority threads can't produce a preferred match.
+                        break;
+                    }
+                    _ => false,
+                };
+                if advance && let Some(c) = c {
+                    let after = text[position + c.len_utf8()..].chars().next();
+                    self.add_thread(
+                        &mut next,
+                        pc + 1,
+                        slots,
+                        position + c.len_utf8(),
+                        Some(c),
+                        after,
+                    );
+                }
+            }
+
+            let Some(c) = c else {
+                break;
+            };
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+            position += c.len_utf8();
+            prev = Some(c);
+        }
+
+        matched
+    }
+
+    /// Adds a thread at `pc`, following jumps, splits, saves and
+    /// assertions so the list only holds threads waiting on input.
+    fn add_thread(
+        &self,
+        threads: &mut Threads,
+        pc: usize,
+        mut slots: Vec<Option<usize>>,
+        position: usize,
+        prev: Option<char>,
+        next: Option<char>,
+    ) {
+        if !threads.visit(pc) {
+            return;
+        }
+
+        match &self.insts[pc] {
+            Inst::Jump(target) => self.add_thread(threads, *target, slots, position, prev, next),
+            Inst::Split(first, second) => {
+                self.add_thread(threads, *first, slots.clone(), position, prev, next);
+                self.add_thread(threads, *second, slots, position, prev, next);
+            }
+            Inst::Save(slot) => {
+                if let Some(saved) = slots.get_mut(*slot) {
+                    *saved = Some(position);
+                }
+                self.add_thread(threads, pc + 1, slots, position, prev, next);
+            }
+            Inst::Assert(assertion) => {
+                let at_boundary = prev.is_some_and(is_word_char) != next.is_some_and(is_word_char);
+                let holds = match assertion {
+                    Assertion::Start => prev.is_none(),
+                    Assertion::End => next.is_none(),
+                    Assertion::WordBoundary => at_boundary,
+                    Assertion::NotWordBoundary => !at_boundary,
+                };
+                if holds {
+                    self.add_thread(threads, pc + 1, slots, position, prev, next);
+                }
+            }
+            _ => threads.push(pc, slots),
+        }
+    }
+}
+
+/// An ordered thread list with a per-step visited set.
+struct Threads {
+    visited: Vec<bool>,
+    list: Vec<(usize, Vec<Option<usize>>)>,
+}
+
+impl Threads {
+    fn new(size: usize) -> Threads {
+        Threads {
+            visited: vec![false; size],
+            list: Vec::new(),
+        }
+    }
+
+    fn visit(&mut self, pc: usize) -> bool {
+        !std::mem::replace(&mut self.visited[pc], true)
+    }
+
+    fn push(&mut self, pc: usize, slots: Vec<Option<usize>>) {
+        self.list.push((pc, slots));
+    }
+
+    fn len(&self) -> usize {
+        self.list.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    fn take(&mut self, i: usize) -> (usize, Vec<Option<usize>>) {
+        let (pc, slots) = &mut self.list[i];
+        (*pc, std::mem::take(slots))
+    }
+
+    fn clear(&mut self) {
+        self.visited.fill(false);
+        self.list.clear();
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn regex(pattern: &str) -> Regex {
+        RegexBuilder::new(pattern).build().unwrap()
+    }
+
+    fn find(pattern: &str, text: &str) -> Option<(usize, usize)> {
+        let m = regex(pattern).find_iter(text).next()?;
+        Some((m.start(), m.end()))
+    }
+
+    fn find_all(pattern: &str, text: &str) -> Vec<(usize, usize)> {
+        regex(pattern)
+            .find_iter(text)
+            .map(|m| (m.start(), m.end()))
+            .collect()
+    }
+
+    fn error(pattern: &str) -> String {
+        RegexBuilder::new(pattern).build().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn alternation_is_leftmost_first() {
+        assert_eq!(find("a|ab", "ab"), Some((0, 1)));
+        assert_eq!(find("ab|a", "ab"), Some((0, 2)));
+        assert_eq!(find("b|a", "ab"), Some((0, 1)));
+        assert_eq!(find("x(a|ab)c", "xabc"), Some((0, 4)));
+    }
+
+    #[test]
+    fn greedy_and_lazy_quantifiers() {
+        assert_eq!(find("a+", "aaa"), Some((0, 3)));
+        assert_eq!(find("a+?", "aaa"), Some((0, 1)));
+        assert_eq!(find("a*?b", "aab"), Some((0, 3)));
+        assert_eq!(find("<.*>", "<a><b>"), Some((0, 6)));
+        assert_eq!(find("<.*?>", "<a><b>"), Some((0, 3)));
+        assert_eq!(find("ab?", "ab"), Some((0, 2)));
+        assert_eq!(find("ab??", "ab"), Some((0, 1)));
+    }
+
+    #[test]
+    fn counted_repetition() {
+        assert_eq!(find("a{2}", "aaa"), Some((0, 2)));
+        assert_eq!(find("a{2,}", "aaaa"), Some((0, 4)));
+        assert_eq!(find("a{1,3}", "aaaa"), Some((0, 3)));
+        assert_eq!(find("a{1,3}?", "aaaa"), Some((0, 1)));
+        assert_eq!(find("a{2,3}", "a"), None);
+        assert_eq!(find("(ab){2}", "ababab"), Some((0, 4)));
+        assert_eq!(find(r#"\{"a"#, r#"{"a":1}"#), Some((0, 3)));
+        assert_eq!(find(r#"{"a"#, r#"{"a":1}"#), Some((0, 3)));
+    }
+
+    #[test]
+    fn word_boundaries() {
+        assert_eq!(find(r"\bcat\b", "concat cat"), Some((7, 10)));
+        assert_eq!(find(r"\Bcat", "concat"), Some((3, 6)));
+        assert_eq!(find(r"\bé\b", "café é"), Some((6, 8)));
+        assert!(!regex(r"\bcat\b").is_match("cats"));
+    }
+
+    #[test]
+    fn classes() {
+        assert_eq!(find("[a-c]+", "xxabcd"), Some((2, 5)));
+        assert_eq!(find("[^0-9]+", "12ab3"), Some((2, 4)));
+        assert_eq!(find(r"\d+", "ab123c"), Some((2, 5)));
+        assert_eq!(find(r"[\w-]+", "a-b c"), Some((0, 3)));
+        assert_eq!(find(r"\S+", "  ab "), Some((2, 4)));
+        assert_eq!(find("[]a]+", "x]a"), Some((1, 3)));
+        assert_eq!(find("[^]a]", "]ab"), Some((2, 3)));
+        assert_eq!(find(".", "\n"), None);
+    }
+
+    #[test]
+    fn case_insensitive() {
+        assert!(regex("(?i)hello").is_match("HeLLo"));
+        assert!(regex("(?i)[a-z]+").is_match("ABC"));
+        assert!(!regex("hello").is_match("HELLO"));
+        let regex = RegexBuilder::new("hello")
+            .case_insensitive(true)
+            .build()
+            .unwrap();
+        assert!(regex.is_match("HELLO"));
+        assert!(regex.is_case_insensitive());
+    }
+
+    #[test]
+    fn anchors() {
+        assert!(!regex("^a").is_match("ba"));
+        assert!(!regex("a$").is_match("ab"));
+        assert!(regex("^ab$").is_match("ab"));
+        assert_eq!(find_all("^a", "aa"), vec![(0, 1)]);
+        assert_eq!(find_all("a$", "aa"), vec![(1, 2)]);
+        assert_eq!(find_all("", "ab"), vec![(0, 0), (1, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn utf8_input() {
+        assert_eq!(find("é+", "caféé"), Some((3, 7)));
+        assert_eq!(find(".", "日本"), Some((0, 3)));
+        assert_eq!(find_all(".", "aé日").len(), 3);
+        assert_eq!(find("[à-ÿ]+", "voilà"), Some((4, 6)));
+        assert_eq!(find_all("", "é"), vec![(0, 0), (2, 2)]);
+    }
+
+    #[test]
+    fn captures() {
+        let regex = regex(r"(\w+)=(\d+)?");
+        let captures: Vec<_> = regex.captures_iter("a=1 b=").collect();
+        assert_eq!(captures.len(), 2);
+        let first = captures[0].get(1).unwrap();
+        assert_eq!((first.start(), first.end()), (0, 1));
+        let second = captures[0].get(2).unwrap();
+        assert_eq!((second.start(), second.end()), (2, 3));
+        assert!(captures[1].get(2).is_none());
+        assert_eq!(captures[1].len(), 3);
+        assert!(regex.is_match("x=1"));
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        assert_eq!(
+            error("["),
+            "regex parse error at 1: unclosed character class"
+        );
+        assert_eq!(
+            error("a{2"),
+            "regex parse error at 3: unclosed counted repetition"
+        );
+        assert_eq!(
+            error("a{2,3"),
+            "regex parse error at 5: unclosed counted repetition"
+        );
+        assert!(error("(a").contains("unclosed group"));
+        assert!(error("a)").contains("unmatched ')'"));
+        assert!(error("*a").contains("nothing to repeat"));
+        assert!(error("a{3,2}").contains("invalid repetition range"));
+        assert!(error("a{1001}").contains("repetition count too large"));
+    }
+
+    #[test]
+    fn oversized_programs_are_rejected() {
+        assert!(error("((a{100}){100}){100}").contains("pattern too large"));
+        assert!(RegexBuilder::new("a{1000}").build().is_ok());
+    }
+}
@@ -0,0 +1,270 @@
+//! The `logview` table exposed to Lua commands and scripts.
+//!
+//! Lua closures can't borrow the `App` while a chunk is executing, so the
+//! state they need lives behind `Rc<RefCell<...>>` handles shared with the
+//! app.
+
+use crate::{
+    StatusMessage,
+    content::Content,
+    regex::{Regex, RegexBuilder},
+};
+use mlua::{Function, Lua, LuaOptions, RegistryKey, StdLib, Table, Value};
+use ratatui::style::Color;
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    path::Path,
+    rc::Rc,
+    str::FromStr,
+};
+
+/// A coloring rule registered with `logview.highlight`.
+pub struct Highlight {
+    pub regex: Regex,
+    pub color: Color,
+}
+
+/// App state shared with the Lua API.
+#[derive(Clone)]
+pub struct Shared {
+    pub content: Rc<RefCell<Content>>,
+    /// View position at the top of the viewport. The app clamps it after
+    /// each script runs.
+    pub scroll_offset: Rc<Cell<usize>>,
+    /// View position of the current line. The app keeps it on screen after
+    /// each script runs.
+    pub cursor_line: Rc<Cell<usize>>,
+    /// Content indices of the lines passing the active filters, if any.
+    pub visible: Rc<RefCell<Option<Vec<usize>>>>,
+    pub status_message: Rc<RefCell<Option<StatusMessage>>>,
+    pub highlights: Rc<RefCell<Vec<Highlight>>>,
+    /// Predicate set with `logview.filter`.
+    pub lua_filter: Rc<RefCell<Option<RegistryKey>>>,
+    /// Set whenever `lua_filter` changes so the app recomputes the view.
+    pub lua_filter_changed: Rc<Cell<bool>>,
+    /// Commands registered with `logview.command`, keyed by name.
+    pub commands: Rc<RefCell<HashMap<String, RegistryKey>>>,
+    /// Hook set with `logview.on_line`.
+    pub on_line: Rc<RefCell<Option<RegistryKey>>>,
+    /// Set whenever `on_line` changes so the app reruns it on every line.
+    pub on_line_changed: Rc<Cell<bool>>,
+}
+
+/// View position of content index `line`, or of the next visible line if it
+/// is filtered out.
+pub fn position_of(visible: Option<&Vec<usize>>, line: usize) -> usize {
+    visible.map_or(line, |visible| {
+        visible.partition_point(|&index| index < line)
+    })
+}
+
+/// Registers the global `logview` table.
+///
+/// - `logview.lines()` returns the log lines as a table of strings. Indices
+///   are 1-based as usual in Lua, so `logview.lines()[1]` is the first line.
+/// - `logview.goto_line(n)` scrolls so the 1-based line `n` is at the top,
+///   and makes it the current line.
+///   It's also registered as `goto`, but since that's a keyword in Lua 5.4
+///   it can only be called as `logview["goto"](n)`.
+/// - `logview.scroll(delta)` scrolls down by `delta` lines (up if negative).
+/// - `logview.current_line()` returns the 1-based number of the current
+///   line, and `logview.line_count()` the total line count.
+/// - `logview.set_status(msg)` shows `msg` in the status line until the next
+///   key press.
+/// - `logview.highlight(pattern, color)` colors text matching the regex
+///   `pattern`. Colors are ratatui names (`"red"`, `"lightblue"`, ...) or
+///   `"#rrggbb"` hex.
+/// - `logview.filter(fn)` shows only lines for which `fn(line, n)` returns
+///   true, where `n` is the 1-based line number. `logview.clear_filter()`
+///   removes it.
+/// - `logview.command(name, fn)` makes `:name args` call `fn(args)`, where
+///   `args` is the rest of the command line as a string.
+/// - `logview.on_line(fn)` calls `fn(n, line)` on each line as it's loaded,
+///   where `n` is the 1-based line number; a string returned replaces the
+///   line, `nil` keeps it. Lines already loaded are run through it too.
+///   `logview.on_line(nil)` removes it.
+pub fn install(lua: &Lua, shared: &Shared) -> mlua::Result<()> {
+    let api = lua.create_table()?;
+
+    let content = shared.content.clone();
+    api.set(
+        "lines",
+        lua.create_function(move |lua, ()| -> mlua::Result<Table> {
+            let content = content.borrow();
+            lua.create_sequence_from((0..content.len()).map(|index| content.line(index)))
+        })?,
+    )?;
+
+    let (scroll_offset, cursor_line, visible) = (
+        shared.scroll_offset.clone(),
+        shared.cursor_line.clone(),
+        shared.visible.clone(),
+    );
+    let goto_line = lua.create_function(move |_, line: usize| {
+        let position = position_of(visible.borrow().as_ref(), line.saturating_sub(1));
+        scroll_offset.set(position);
+        cursor_line.set(position);
+        Ok(())
+    })?;
+    api.set("goto", goto_line.clone())?;
+    api.set("goto_line", goto_line)?;
+
+    let scroll_offset = shared.scroll_offset.clone();
+    api.set(
+        "scroll",
+        lua.create_function(move |_, delta: isize| {
+            scroll_offset.set(scroll_offset.get().saturating_add_signed(delta));
+            Ok(())
+        })?,
+    )?;
+
+    let (cursor_line, visible, content) = (
+        shared.cursor_line.clone(),
+        shared.visible.clone(),
+        shared.content.clone(),
+    );
+    api.set(
+        "current_line",
+        lua.create_function(move |_, ()| {
+            let position = cursor_line.get();
+            let line = match visible.borrow().as_ref() {
+                Some(visible) => visible.get(position).copied(),
+                None => (position < content.borrow().len()).then_some(position),
+            };
+            // Matches what's on screen: nothing when the view is empty.
+            Ok(line.map(|line| line + 1))
+        })?,
+    )?;
+
+    let content = shared.content.clone();
+    api.set(
+        "line_count",
+        lua.create_function(move |_, ()| Ok(content.borrow().len()))?,
+    )?;
+
+    let status_message = shared.status_message.clone();
+    api.set(
+        "set_status",
+        lua.create_function(move |_, message: String| {
+            status_message.replace(Some(StatusMessage::info(message)));
+            Ok(())
+        })?,
+    )?;
+
+    let highlights = shared.highlights.clone();
+    api.set(
+        "highlight",
+        lua.create_function(move |_, (pattern, color): (String, String)| {
+            let regex = RegexBuilder::new(&pattern)
+                .build()
+                .map_err(mlua::Error::external)?;
+            let color = Color::from_str(&color)
+                .map_err(|_| mlua::Error::external(format!("invalid color '{color}'")))?;
+            highlights.borrow_mut().push(Highlight { regex, color });
+            Ok(())
+        })?,
+    )?;
+
+    let (lua_filter, changed) = (shared.lua_filter.clone(), shared.lua_filter_changed.clone());
+    api.set(
+        "filter",
+        lua.create_function(move |lua, predicate: Function| {
+            lua_filter.replace(Some(lua.create_registry_value(predicate)?));
+            changed.set(true);
+            Ok(())
+        })?,
+    )?;
+
+    let (lua_filter, changed) = (shared.lua_filter.clone(), shared.lua_filter_changed.clone());
+    api.set(
+        "clear_filter",
+        lua.create_function(move |_, ()| {
+            lua_filter.replace(None);
+            changed.set(true);
+            Ok(())
+        })?,
+    )?;
+
+    let commands = shared.commands.clone();
+    api.set(
+        "command",
+        lua.create_function(move |lua, (name, function): (String, Function)| {
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                return Err(mlua::Error::external(format!(
+                    "invalid command name '{name}'"
+                )));
+            }
+            let key = lua.create_registry_value(function)?;
+            commands.borrow_mut().insert(name, key);
+            Ok(())
+        })?,
+    )?;
+
+    let (on_line, changed) = (shared.on_line.clone(), shared.on_line_changed.clone());
+    api.set(
+        "on_line",
+        lua.create_function(move |lua, hook: Option<Function>| {
+            let key = hook
+                .map(|hook| lua.create_registry_value(hook))
+                .transpose()?;
+            on_line.replace(key);
+            changed.set(true);
+            Ok(())
+        })?,
+    )?;
+
+    lua.globals().set("logview", api)
+}
+
+/// Creates the Lua state. A safe one has only the string, math and table
+/// libraries, and none of the base functions that load code from files or
+/// strings, so scripts can't touch the system beyond the `logview` API.
+pub fn new_lua(safe: bool) -> mlua::Result<Lua> {
+    if !safe {
+        return Ok(Lua::new());
+    }
+    let lua = Lua::new_with(
+        StdLib::STRING | StdLib::MATH | StdLib::TABLE,
+        LuaOptions::default(),
+    )?;
+    let globals = lua.globals();
+    for name in ["dofile", "loadfile", "load"] {
+        globals.set(name, Value::Nil)?;
+    }
+    drop(globals);
+    Ok(lua)
+}
+
+/// Makes `require` search `dir` for modules, as `dir/name.lua` or
+/// `dir/name/init.lua`, before the directories it already searched.
+pub fn add_to_path(lua: &Lua, dir: &Path) -> mlua::Result<()> {
+    // Safe states have no `require`.
+    let Some(package) = lua.globals().get::<_, Option<Table>>("package")? else {
+        return Ok(());
+    };
+    let path: String = package.get("path")?;
+    let dir = dir.to_string_lossy();
+    let dir = if dir.is_empty() { "." } else { dir.as_ref() };
+    let patterns = format!("{dir}/?.lua;{dir}/?/init.lua");
+    if path
+        .split(';')
+        .any(|pattern| pattern == format!("{dir}/?.lua"))
+    {
+        return Ok(());
+    }
+    package.set("path", format!("{patterns};{path}"))
+}
+
+/// The message of a Lua error on one line for the status line, without the
+/// stack traceback. The paths a failed `require` searched are kept.
+pub fn error_message(err: &mlua::Error) -> String {
+    let text = err.to_string();
+    let message = text.split("\nstack traceback:").next().unwrap_or_default();
+    message
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
@@ -0,0 +1,159 @@
+//! The position, bookmarks and marks of each file viewed, remembered
+//! between runs in `~/.local/state/logview/sessions.yml`.
+
+use crate::{gzip, watch};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs::{self, File},
+    io::{self, Read},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Files remembered at most; the least recently saved are forgotten.
+const MAX_SESSIONS: usize = 100;
+
+/// Bytes at the start of a file checksummed to recognize it again.
+const HEAD_SIZE: u64 = 4096;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Session {
+    /// Modification time and length of the file when saved, to tell
+    /// whether it was rewritten since.
+    modified: u64,
+    len: u64,
+    /// Inode of the file and checksum of its first bytes when saved, to
+    /// tell whether it's still the same file.
+    #[serde(default)]
+    inode: u64,
+    #[serde(default)]
+    head: u32,
+    /// When the session was saved, for forgetting old ones.
+    saved: u64,
+    /// Line at the top of the viewport, as a content index.
+    pub top: usize,
+    /// The current line, as a content index.
+    pub cursor: usize,
+    #[serde(default)]
+    pub bookmarks: Vec<usize>,
+    #[serde(default)]
+    pub marks: HashMap<char, usize>,
+}
+
+impl Session {
+    pub fn new(
+        top: usize,
+        cursor: usize,
+        bookmarks: Vec<usize>,
+        marks: HashMap<char, usize>,
+    ) -> Session {
+        Session {
+            top,
+            cursor,
+            bookmarks,
+            marks,
+            ..Session::default()
+        }
+    }
+}
+
+/// The session saved for `file`, unless the file was rewritten since: its
+/// modification time changed, and it isn't the same file, by its inode and
+/// first bytes, only grown as logs do.
+pub fn load(file: &Path) -> Option<Session> {
+    let file = fs::canonicalize(file).ok()?;
+    let (modified, len, inode) = stat(&file)?;
+    let session = read_all().remove(&file)?;
+    let grown = || {
+        session.len <= len
+            && session.inode == inode
+            && head(&file, session.len).is_some_and(|head| head == session.head)
+    };
+    (session.modified == modified || grown()).then_some(session)
+}
+
+/// Remembers `session` for `file`.
+pub fn save(file: &Path, mut session: Session) -> io::Result<()> {
+    let Some(path) = sessions_path() else {
+        return Ok(());
+    };
+    let file = fs::canonicalize(file)?;
+    let Some((modified, len, inode)) = stat(&file) else {
+        return Ok(());
+    };
+    session.modified = modified;
+    session.len = len;
+    session.inode = inode;
+    session.head = head(&file, len).unwrap_or_default();
+    session.saved = seconds(SystemTime::now());
+
+    let mut sessions = read_all();
+    sessions.insert(file, session);
+    while sessions.len() > MAX_SESSIONS {
+        let oldest = sessions
+            .iter()
+            .min_by_key(|(_, session)| session.saved)
+            .map(|(file, _)| file.clone());
+        if let Some(oldest) = oldest {
+            sessions.remove(&oldest);
+        }
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let yaml = serde_yaml::to_string(&sessions).map_err(io::Error::other)?;
+    fs::write(path, yaml)
+}
+
+/// Files with a saved session that still exist, the most recently viewed
+/// first.
+pub fn recent_files() -> Vec<PathBuf> {
+    let mut sessions: Vec<(PathBuf, Session)> = read_all().into_iter().collect();
+    sessions.sort_by_key(|(_, session)| std::cmp::Reverse(session.saved));
+    sessions
+        .into_iter()
+        .map(|(file, _)| file)
+        .filter(|file| file.exists())
+        .collect()
+}
+
+fn sessions_path() -> Option<PathBuf> {
+    let dir = dirs::state_dir().or_else(dirs::data_local_dir)?;
+    Some(dir.join("logview").join("sessions.yml"))
+}
+
+/// Every saved session by file, or none if they can't be read.
+fn read_all() -> BTreeMap<PathBuf, Session> {
+    sessions_path()
+        .and_then(|path| fs::read_to_string(path).ok())
+        .and_then(|yaml| serde_yaml::from_str(&yaml).ok())
+        .unwrap_or_default()
+}
+
+/// Modification time in seconds, length and inode of `file`.
+fn stat(file: &Path) -> Option<(u64, u64, u64)> {
+    let metadata = fs::metadata(file).ok()?;
+    Some((
+        seconds(metadata.modified().ok()?),
+        metadata.len(),
+        watch::inode(&metadata),
+    ))
+}
+
+/// Checksum of the first bytes of `file`, at most `len` of them.
+fn head(file: &Path, len: u64) -> Option<u32> {
+    let mut bytes = Vec::new();
+    File::open(file)
+        .ok()?
+        .take(len.min(HEAD_SIZE))
+        .read_to_end(&mut bytes)
+        .ok()?;
+    Some(gzip::crc32(&bytes))
+}
+
+fn seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs())
+}
@@ -0,0 +1,103 @@
+//! The summary of the lines in view that `:stats` shows.
+
+use crate::{level::Level, theme::Theme, timestamp};
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Modifier, Style},
+    text::{Line, Span},
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Default)]
+pub struct Stats {
+    /// Lines in view counted so far, from the top. Lines appended while
+    /// the summary is open are counted as they arrive.
+    pub counted: usize,
+    levels: BTreeMap<Level, usize>,
+    /// Times of the first and last timestamped lines.
+    first_time: Option<i64>,
+    last_time: Option<i64>,
+}
+
+impl Stats {
+    /// Counts the next line in view.
+    pub fn add(&mut self, level: Option<Level>, time: Option<i64>) {
+        self.counted += 1;
+        if let Some(level) = level {
+            *self.levels.entry(level).or_default() += 1;
+        }
+        if time.is_some() {
+            self.first_time = self.first_time.or(time);
+            self.last_time = time;
+        }
+    }
+
+    /// Draws the summary in the middle of `area`.
+    pub fn render(&self, f: &mut Frame, area: Rect, theme: &Theme) {
+        let heading = Style::default().add_modifier(Modifier::BOLD);
+        let row = |name: &str, count: usize, style: Style| {
+            let percent = if self.counted > 0 {
+                count as f64 * 100.0 / self.counted as f64
+            } else {
+                0.0
+            };
+            Line::from(vec![
+                Span::styled(format!("  {name:<10}"), style),
+                Span::raw(format!("{count:>12} {percent:>6.1}%")),
+            ])
+        };
+
+        let mut lines = vec![
+            Line::styled("Lines", heading),
+            row("total", self.counted, Style::default()),
+        ];
+        // Most severe first.
+        for (level, &count) in self.levels.iter().rev() {
+            lines.push(row(level.name(), count, level.style(theme)));
+        }
+        let unleveled = self.counted - self.levels.values().sum::<usize>();
+        lines.push(row(
+            "no level",
+            unleveled,
+            Style::default().fg(theme.line_number),
+        ));
+
+        if let (Some(first), Some(last)) = (self.first_time, self.last_time) {
+            lines.push(Line::default());
+            lines.push(Line::styled("Time", heading));
+            let span = last - first;
+            lines.push(Line::raw(format!(
+                "  {:<10}{:>12}",
+                "span",
+                timestamp::format_duration(span)
+            )));
+            if span > 0 {
+                let rate = self.counted as f64 * 1000.0 / span as f64;
+                // Quiet logs read better per minute.
+                let (unit, rate) = if rate >= 1.0 {
+                    ("lines/s", rate)
+                } else {
+                    ("lines/min", rate * 60.0)
+                };
+                lines.push(Line::raw(format!("  {unit:<10}{rate:>12.2}")));
+            }
+        }
+
+        let width = 40.min(area.width);
+        let height = (lines.len() as u16 + 2).min(area.height);
+        let popup = Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + (area.height - height) / 2,
+            width,
+            height,
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title("Stats")
+            .title_bottom(Line::from(" any key to close ").right_aligned());
+        f.render_widget(Clear, popup);
+        f.render_widget(Paragraph::new(lines).block(block), popup);
+    }
+}
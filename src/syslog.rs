@@ -0,0 +1,234 @@
+//! Parsing of syslog lines, in the classic BSD format of RFC 3164 that
+//! `/var/log/syslog` uses, with or without the `<priority>` header, and in
+//! the newer RFC 5424 format.
+
+use crate::level::Level;
+use std::str::FromStr;
+
+/// Lines sampled from the start of a file to tell whether it's syslog.
+pub const DETECT_LINES: usize = 20;
+
+const MONTHS: &[&str] = &[
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Facility names by code, as `syslog.conf` spells them.
+const FACILITIES: &[&str] = &[
+    "kern",
+    "user",
+    "mail",
+    "daemon",
+    "auth",
+    "syslog",
+    "lpr",
+    "news",
+    "uucp",
+    "cron",
+    "authpriv",
+    "ftp",
+    "ntp",
+    "security",
+    "console",
+    "solaris-cron",
+    "local0",
+    "local1",
+    "local2",
+    "local3",
+    "local4",
+    "local5",
+    "local6",
+    "local7",
+];
+
+const SEVERITIES: &[&str] = &[
+    "emerg", "alert", "crit", "err", "warning", "notice", "info", "debug",
+];
+
+/// The format of the lines of a file, chosen with `--format`; without it,
+/// syslog is detected from the first lines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Syslog,
+    /// Lines as they are, never parsed as syslog.
+    Plain,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Format, String> {
+        match name.to_ascii_lowercase().as_str() {
+            "syslog" => Ok(Format::Syslog),
+            "plain" | "raw" => Ok(Format::Plain),
+            _ => Err(format!(
+                "unknown format '{name}' (expected syslog or plain)"
+            )),
+        }
+    }
+}
+
+/// A syslog line split into its parts. Parts missing from the line, or
+/// given as `-` in RFC 5424, are empty.
+pub struct Message<'a> {
+    /// Facility and severity, when the line has a `<priority>` header.
+    pub priority: Option<u8>,
+    pub timestamp: &'a str,
+    pub host: &'a str,
+    /// The program, or RFC 5424's app name.
+    pub tag: &'a str,
+    pub pid: &'a str,
+    pub text: &'a str,
+}
+
+impl<'a> Message<'a> {
+    pub fn parse(line: &'a str) -> Option<Message<'a>> {
+        let (priority, rest) = match line.strip_prefix('<') {
+            Some(after) => {
+                let end = after.find('>')?;
+                let priority = after[..end].parse::<u8>().ok().filter(|&p| p < 192)?;
+                (Some(priority), &after[end + 1..])
+            }
+            None => (None, line),
+        };
+        match rest.strip_prefix("1 ") {
+            Some(rest) if priority.is_some() => parse_5424(priority, rest),
+            _ => parse_3164(priority, rest),
+        }
+    }
+
+    /// The level from the severity of the priority, or else from the
+    /// message, leaving out the host and tag.
+    pub fn level(&self) -> Option<Level> {
+        match self.priority.map(|priority| priority & 7) {
+            Some(0..=2) => Some(Level::Fatal),
+            Some(3) => Some(Level::Error),
+            Some(4) => Some(Level::Warn),
+            Some(5 | 6) => Some(Level::Info),
+            Some(_) => Some(Level::Debug),
+            None => Level::detect(self.text),
+        }
+    }
+
+    /// The parts as key/value pairs for the column view.
+    pub fn fields(&self) -> Vec<(String, String)> {
+        let mut fields = Vec::new();
+        if let Some(priority) = self.priority {
+            let facility = FACILITIES
+                .get(usize::from(priority >> 3))
+                .map_or_else(|| (priority >> 3).to_string(), |name| name.to_string());
+            fields.push(("facility".to_string(), facility));
+            let severity = SEVERITIES[usize::from(priority & 7)];
+            fields.push(("severity".to_string(), severity.to_string()));
+        }
+        for (key, value) in [
+            ("timestamp", self.timestamp),
+            ("host", self.host),
+            ("tag", self.tag),
+            ("pid", self.pid),
+            ("msg", self.text),
+        ] {
+            fields.push((key.to_string(), value.to_string()));
+        }
+        fields
+    }
+}
+
+/// `Mmm dd hh:mm:ss host tag[pid]: text`, or with an ISO 8601 timestamp as
+/// rsyslog writes by default.
+fn parse_3164(priority: Option<u8>, line: &str) -> Option<Message<'_>> {
+    let timestamp_len = if is_bsd_timestamp(line.as_bytes()) {
+        15
+    } else if line.get(..4)?.bytes().all(|byte| byte.is_ascii_digit())
+        && line.as_bytes().get(10) == Some(&b'T')
+    {
+        line.find(' ')?
+    } else {
+        return None;
+    };
+    let timestamp = &line[..timestamp_len];
+    let rest = line[timestamp_len..].strip_prefix(' ')?;
+    let (host, rest) = rest.split_once(' ')?;
+    if host.is_empty() {
+        return None;
+    }
+
+    let tag_end = rest.find([':', '[', ' ']).unwrap_or(rest.len());
+    let tag = &rest[..tag_end];
+    let mut rest = &rest[tag_end..];
+    let mut pid = "";
+    if let Some(after) = rest.strip_prefix('[') {
+        let end = after.find(']')?;
+        pid = &after[..end];
+        rest = &after[end + 1..];
+    }
+    let text = rest.strip_prefix(':')?;
+    if tag.is_empty() {
+        return None;
+    }
+    Some(Message {
+        priority,
+        timestamp,
+        host,
+        tag,
+        pid,
+        text: text.strip_prefix(' ').unwrap_or(text),
+    })
+}
+
+/// Whether `line` starts with a timestamp like `Jan  2 15:04:05 `.
+fn is_bsd_timestamp(line: &[u8]) -> bool {
+    let Some(timestamp) = line.get(..16) else {
+        return false;
+    };
+    let digit = |i: usize| timestamp[i].is_ascii_digit();
+    MONTHS
+        .iter()
+        .any(|month| month.as_bytes() == &timestamp[..3])
+        && timestamp[3] == b' '
+        && (timestamp[4] == b' ' || digit(4))
+        && digit(5)
+        && timestamp[6] == b' '
+        && digit(7)
+        && digit(8)
+        && timestamp[9] == b':'
+        && digit(10)
+        && digit(11)
+        && timestamp[12] == b':'
+        && digit(13)
+        && digit(14)
+        && timestamp[15] == b' '
+}
+
+/// `timestamp host app procid msgid [data] text`, after `<priority>1 `.
+fn parse_5424(priority: Option<u8>, line: &str) -> Option<Message<'_>> {
+    let mut parts = line.splitn(5, ' ');
+    let mut part = || parts.next().map(|part| if part == "-" { "" } else { part });
+    let (timestamp, host, tag, pid) = (part()?, part()?, part()?, part()?);
+    // The message ID and structured data stay in the text.
+    let text = parts.next()?;
+    Some(Message {
+        priority,
+        timestamp,
+        host,
+        tag,
+        pid,
+        text: text.strip_prefix('\u{feff}').unwrap_or(text),
+    })
+}
+
+/// Whether most of the first non-empty `lines` are syslog.
+pub fn detect<S: AsRef<str>>(lines: impl IntoIterator<Item = S>) -> bool {
+    let (mut sampled, mut parsed) = (0, 0);
+    for line in lines {
+        let line = line.as_ref();
+        if line.trim().is_empty() {
+            continue;
+        }
+        sampled += 1;
+        parsed += usize::from(Message::parse(line).is_some());
+        if sampled == DETECT_LINES {
+            break;
+        }
+    }
+    parsed * 2 > sampled
+}
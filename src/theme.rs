@@ -0,0 +1,111 @@
+//! Colors of the interface, from the config's `theme` section.
+
+use ratatui::style::Color;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// The `theme` section of the config. Colors are names (`red`,
+/// `lightblue`), hex (`#ff8800`) or 256-color indices (`208`); unset ones
+/// keep their defaults.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ThemeConfig {
+    #[serde(default)]
+    pub border_color: Option<String>,
+    #[serde(default)]
+    pub error_color: Option<String>,
+    #[serde(default)]
+    pub warn_color: Option<String>,
+    #[serde(default)]
+    pub debug_color: Option<String>,
+    #[serde(default)]
+    pub match_highlight_fg: Option<String>,
+    #[serde(default)]
+    pub match_highlight_bg: Option<String>,
+    #[serde(default)]
+    pub line_number_color: Option<String>,
+    #[serde(default)]
+    pub bookmark_color: Option<String>,
+    #[serde(default)]
+    pub cursor_line_color: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub border: Color,
+    /// Error and fatal lines, and error messages.
+    pub error: Color,
+    pub warn: Color,
+    /// Debug and trace lines, which matter least.
+    pub debug: Color,
+    pub match_fg: Color,
+    pub match_bg: Color,
+    /// Line numbers and other gutter and marker text.
+    pub line_number: Color,
+    pub bookmark: Color,
+    /// Background of the current line.
+    pub cursor_line: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            border: Color::Yellow,
+            error: Color::Red,
+            warn: Color::Yellow,
+            debug: Color::DarkGray,
+            match_fg: Color::Black,
+            match_bg: Color::Yellow,
+            line_number: Color::DarkGray,
+            bookmark: Color::Cyan,
+            cursor_line: Color::Indexed(236),
+        }
+    }
+}
+
+impl Theme {
+    /// Builds the theme from `config`. Invalid colors keep their default
+    /// and are described in the returned warnings.
+    pub fn from_config(config: &ThemeConfig) -> (Theme, Vec<String>) {
+        let mut theme = Theme::default();
+        let mut warnings = Vec::new();
+        let mut set = |color: &mut Color, name: &str, value: &Option<String>| {
+            let Some(value) = value else {
+                return;
+            };
+            match Color::from_str(value) {
+                Ok(parsed) => *color = parsed,
+                Err(_) => warnings.push(format!("theme: invalid {name} '{value}'")),
+            }
+        };
+        set(&mut theme.border, "border_color", &config.border_color);
+        set(&mut theme.error, "error_color", &config.error_color);
+        set(&mut theme.warn, "warn_color", &config.warn_color);
+        set(&mut theme.debug, "debug_color", &config.debug_color);
+        set(
+            &mut theme.match_fg,
+            "match_highlight_fg",
+            &config.match_highlight_fg,
+        );
+        set(
+            &mut theme.match_bg,
+            "match_highlight_bg",
+            &config.match_highlight_bg,
+        );
+        set(
+            &mut theme.line_number,
+            "line_number_color",
+            &config.line_number_color,
+        );
+        set(
+            &mut theme.bookmark,
+            "bookmark_color",
+            &config.bookmark_color,
+        );
+        set(
+            &mut theme.cursor_line,
+            "cursor_line_color",
+            &config.cursor_line_color,
+        );
+        (theme, warnings)
+    }
+}
@@ -0,0 +1,358 @@
+//! Parsing of the timestamps log lines start with, and the time between
+//! them, for `:set reltime`.
+//!
+//! Timestamps are read with a small strftime-like format rather than a
+//! date library, since only the few fields logs use are needed. Those
+//! without a time zone are taken as local time.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Formats tried when the config doesn't give one: ISO 8601 with a `T` or
+/// a space, and syslog's.
+const DEFAULT_FORMATS: &[&str] = &[
+    "%Y-%m-%dT%H:%M:%S%z",
+    "%Y-%m-%d %H:%M:%S%z",
+    "%b %e %H:%M:%S",
+];
+
+/// Formats tried for the times given to `:since`, besides the parser's:
+/// ISO 8601 without seconds, and a bare date.
+const CUTOFF_FORMATS: &[&str] = &[
+    "%Y-%m-%dT%H:%M:%S%z",
+    "%Y-%m-%d %H:%M:%S%z",
+    "%Y-%m-%dT%H:%M%z",
+    "%Y-%m-%d %H:%M%z",
+    "%Y-%m-%d",
+];
+
+const MONTHS: &[&str] = &[
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+const MS_PER_DAY: i64 = 24 * 60 * 60 * 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Item {
+    /// `%Y`, four digits.
+    Year,
+    /// `%m`, 1 or 2 digits.
+    Month,
+    /// `%b`, an English month abbreviation.
+    MonthName,
+    /// `%d` or `%e`, 1 or 2 digits, optionally padded with a space.
+    Day,
+    /// `%H`
+    Hour,
+    /// `%M`
+    Minute,
+    /// `%S`, with an optional fraction after `.` or `,`.
+    Second,
+    /// `%z`, an optional `Z` or `+hh:mm` style offset.
+    Zone,
+    Literal(char),
+}
+
+/// Reads the timestamp at the start of lines in any of its formats.
+pub struct Parser {
+    formats: Vec<Vec<Item>>,
+    /// Offset of local time from UTC, for timestamps without a zone.
+    local_offset_ms: i64,
+    /// Local year now, for timestamps without a year.
+    year: i64,
+}
+
+impl Parser {
+    /// Parser for `format`, e.g. `%Y-%m-%d %H:%M:%S`, or for the common
+    /// ISO 8601 and syslog formats if `None`.
+    pub fn new(format: Option<&str>) -> Result<Parser, String> {
+        let formats = match format {
+            Some(format) => vec![compile(format)?],
+            None => DEFAULT_FORMATS
+                .iter()
+                .map(|format| compile(format))
+                .collect::<Result<_, _>>()?,
+        };
+        let local_offset_ms = local_offset_seconds() * 1000;
+        let (year, _, _) = civil_from_days((now_ms() + local_offset_ms).div_euclid(MS_PER_DAY));
+        Ok(Parser {
+            formats,
+            local_offset_ms,
+            year,
+        })
+    }
+
+    /// Milliseconds since the Unix epoch of the timestamp `line` starts
+    /// with, after any spaces or `[`.
+    pub fn parse(&self, line: &str) -> Option<i64> {
+        let line = line.trim_start_matches([' ', '[']);
+        self.formats
+            .iter()
+            .find_map(|format| self.parse_format(format, line))
+            .map(|(time, _)| time)
+    }
+
+    /// Milliseconds since the Unix epoch of `text`, a time ago like `10m`
+    /// or `1h30m`, or a timestamp in the parser's formats or ISO 8601 with
+    /// or without the time.
+    pub fn parse_cutoff(&self, text: &str) -> Result<i64, String> {
+        if let Some(ago) = parse_duration(text) {
+            return Ok(now_ms() - ago);
+        }
+        let cutoff_formats = CUTOFF_FORMATS
+            .iter()
+            .map(|format| compile(format).expect("cutoff formats are valid"));
+        self.formats
+            .iter()
+            .cloned()
+            .chain(cutoff_formats)
+            .find_map(|format| match self.parse_format(&format, text) {
+                Some((time, 0)) => Some(time),
+                _ => None,
+            })
+            .ok_or_else(|| format!("invalid time '{text}' (expected e.g. 10m or 2024-01-01T00:00)"))
+    }
+
+    /// `ms` since the Unix epoch in local time, e.g. `2024-01-31 14:05:00`.
+    pub fn format_local(&self, ms: i64) -> String {
+        let local = ms + self.local_offset_ms;
+        let (year, month, day) = civil_from_days(local.div_euclid(MS_PER_DAY));
+        let seconds = local.rem_euclid(MS_PER_DAY) / 1000;
+        format!(
+            "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    }
+
+    /// The time `line` starts with in `format`, and the number of bytes
+    /// after it.
+    fn parse_format(&self, format: &[Item], line: &str) -> Option<(i64, usize)> {
+        let mut rest = line.as_bytes();
+        let (mut year, mut month, mut day) = (None, 1, 1);
+        let (mut hour, mut minute, mut second, mut millis) = (0, 0, 0, 0);
+        let mut offset = None;
+        for item in format {
+            match item {
+                Item::Year => year = Some(number(&mut rest, 4, 4)?),
+                Item::Month => month = number(&mut rest, 1, 2)?,
+                Item::MonthName => {
+                    let name = rest.get(..3)?;
+                    let index = MONTHS
+                        .iter()
+                        .position(|month| month.as_bytes().eq_ignore_ascii_case(name))?;
+                    month = index as i64 + 1;
+                    rest = &rest[3..];
+                }
+                Item::Day => {
+                    if rest.first() == Some(&b' ') {
+                        rest = &rest[1..];
+                    }
+                    day = number(&mut rest, 1, 2)?;
+                }
+                Item::Hour => hour = number(&mut rest, 2, 2)?,
+                Item::Minute => minute = number(&mut rest, 2, 2)?,
+                Item::Second => {
+                    second = number(&mut rest, 2, 2)?;
+                    if let [b'.' | b',', after @ ..] = rest
+                        && after.first().is_some_and(u8::is_ascii_digit)
+                    {
+                        let digits = after
+                            .iter()
+                            .take_while(|byte| byte.is_ascii_digit())
+                            .count();
+                        // Milliseconds from the first three digits, padded.
+                        millis = after[..digits]
+                            .iter()
+                            .chain(b"000")
+                            .take(3)
+                            .fold(0, |millis, digit| millis * 10 + i64::from(digit - b'0'));
+                        rest = &after[digits..];
+                    }
+                }
+                Item::Zone => offset = zone(&mut rest),
+                Item::Literal(c) => {
+                    let mut buffer = [0; 4];
+                    rest = rest.strip_prefix(c.encode_utf8(&mut buffer).as_bytes())?;
+                }
+            }
+        }
+        if !(1..=12).contains(&month)
+            || !(1..=31).contains(&day)
+            || hour > 23
+            || minute > 59
+            || second > 60
+        {
+            return None;
+        }
+
+        let time = |year| {
+            let days = days_from_civil(year, month, day);
+            days * MS_PER_DAY + ((hour * 60 + minute) * 60 + second) * 1000 + millis
+                - offset.unwrap_or(self.local_offset_ms)
+        };
+        let time = match year {
+            Some(year) => time(year),
+            // Without a year, a date later than today is from last year.
+            None => {
+                let this_year = time(self.year);
+                if this_year > now_ms() + MS_PER_DAY {
+                    time(self.year - 1)
+                } else {
+                    this_year
+                }
+            }
+        };
+        Some((time, rest.len()))
+    }
+}
+
+fn compile(format: &str) -> Result<Vec<Item>, String> {
+    let mut items = Vec::new();
+    let mut chars = format.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            items.push(Item::Literal(c));
+            continue;
+        }
+        items.push(match chars.next() {
+            Some('Y') => Item::Year,
+            Some('m') => Item::Month,
+            Some('b') => Item::MonthName,
+            Some('d' | 'e') => Item::Day,
+            Some('H') => Item::Hour,
+            Some('M') => Item::Minute,
+            Some('S') => Item::Second,
+            Some('z') => Item::Zone,
+            Some('%') => Item::Literal('%'),
+            Some(other) => return Err(format!("timestamp format: unknown %{other}")),
+            None => return Err("timestamp format: trailing %".to_string()),
+        });
+    }
+    if items.iter().all(|item| matches!(item, Item::Literal(_))) {
+        return Err(format!("timestamp format: no fields in '{format}'"));
+    }
+    Ok(items)
+}
+
+/// Reads between `min` and `max` decimal digits.
+fn number(rest: &mut &[u8], min: usize, max: usize) -> Option<i64> {
+    let len = rest
+        .iter()
+        .take(max)
+        .take_while(|byte| byte.is_ascii_digit())
+        .count();
+    if len < min {
+        return None;
+    }
+    let value = rest[..len]
+        .iter()
+        .fold(0, |value, digit| value * 10 + i64::from(digit - b'0'));
+    *rest = &rest[len..];
+    Some(value)
+}
+
+/// Reads an optional `Z`, `+hh:mm`, `+hhmm` or `+hh` zone, returning its
+/// offset from UTC in milliseconds.
+fn zone(rest: &mut &[u8]) -> Option<i64> {
+    let sign = match rest.first()? {
+        b'Z' => {
+            *rest = &rest[1..];
+            return Some(0);
+        }
+        b'+' => 1,
+        b'-' => -1,
+        _ => return None,
+    };
+    let mut after = &rest[1..];
+    let hours = number(&mut after, 2, 2)?;
+    if after.first() == Some(&b':') {
+        after = &after[1..];
+    }
+    let minutes = number(&mut after, 2, 2).unwrap_or(0);
+    *rest = after;
+    Some(sign * (hours * 60 + minutes) * 60 * 1000)
+}
+
+/// Days since 1970-01-01 of a date in the proleptic Gregorian calendar.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// The year, month and day of `days` since 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+pub fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as i64)
+}
+
+fn local_offset_seconds() -> i64 {
+    // `time` and `localtime_r` only write to the values passed.
+    unsafe {
+        let now = libc::time(std::ptr::null_mut());
+        let mut tm: libc::tm = std::mem::zeroed();
+        if libc::localtime_r(&now, &mut tm).is_null() {
+            return 0;
+        }
+        tm.tm_gmtoff
+    }
+}
+
+/// Milliseconds in a duration like `90s`, `10m` or `1h30m`, made of
+/// numbers each followed by `ms`, `s`, `m`, `h` or `d`.
+pub fn parse_duration(text: &str) -> Option<i64> {
+    let mut rest = text.as_bytes();
+    let mut total: i64 = 0;
+    while !rest.is_empty() {
+        let number = number(&mut rest, 1, 9)?;
+        let (unit, len) = match rest {
+            [b'm', b's', ..] => (1, 2),
+            [b's', ..] => (1000, 1),
+            [b'm', ..] => (60 * 1000, 1),
+            [b'h', ..] => (60 * 60 * 1000, 1),
+            [b'd', ..] => (MS_PER_DAY, 1),
+            _ => return None,
+        };
+        total = total.checked_add(number.checked_mul(unit)?)?;
+        rest = &rest[len..];
+    }
+    (!text.is_empty()).then_some(total)
+}
+
+/// A duration in milliseconds in its two largest units, e.g. `5m03s`.
+pub fn format_duration(ms: i64) -> String {
+    let sign = if ms < 0 { "-" } else { "" };
+    let ms = ms.unsigned_abs();
+    let seconds = ms / 1000;
+    let text = match seconds {
+        0 => format!("{ms}ms"),
+        1..60 => format!("{seconds}.{}s", ms % 1000 / 100),
+        60..3600 => format!("{}m{:02}s", seconds / 60, seconds % 60),
+        3600..86400 => format!("{}h{:02}m", seconds / 3600, seconds % 3600 / 60),
+        _ => format!("{}d{:02}h", seconds / 86400, seconds % 86400 / 3600),
+    };
+    format!("{sign}{text}")
+}
@@ -0,0 +1,131 @@
+//! Background watching of the open file for changes made out of band.
+//!
+//! The watcher polls the file's metadata rather than subscribing to OS
+//! notifications, which behaves the same on every platform and filesystem
+//! and is cheap at this rate.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver},
+    },
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// How often the file's metadata is checked.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Longest a burst of writes can postpone reporting a change.
+const MAX_DEBOUNCE: Duration = Duration::from_millis(300);
+
+/// Ordered by how much rereading they need, so merging changes keeps the
+/// greatest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Change {
+    /// The file grew; only the new data needs reading.
+    Appended,
+    /// The file was truncated, replaced or rewritten in place (or removed).
+    Rewritten,
+}
+
+/// Watches a file on a background thread, which stops when this is dropped.
+pub struct Watcher {
+    changes: Receiver<Change>,
+    stop: Arc<AtomicBool>,
+}
+
+impl Watcher {
+    pub fn new(path: PathBuf) -> Watcher {
+        let (sender, changes) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+
+        thread::spawn(move || {
+            let mut last = Snapshot::take(&path);
+            // A change is reported once the file has been quiet for a poll,
+            // so a burst of writes causes a single reload.
+            let mut pending: Option<(Change, Instant)> = None;
+            while !stopped.load(Ordering::Relaxed) {
+                thread::sleep(POLL_INTERVAL);
+                let current = Snapshot::take(&path);
+                if current != last {
+                    let change = Snapshot::classify(last.as_ref(), current.as_ref());
+                    pending = Some(match pending {
+                        Some((earlier, since)) => (earlier.max(change), since),
+                        None => (change, Instant::now()),
+                    });
+                    last = current;
+                    if pending.is_some_and(|(_, since)| since.elapsed() < MAX_DEBOUNCE) {
+                        continue;
+                    }
+                }
+                if let Some((change, _)) = pending.take()
+                    && sender.send(change).is_err()
+                {
+                    return;
+                }
+            }
+        });
+
+        Watcher { changes, stop }
+    }
+
+    /// Returns the change reported since the last call, if any, merging
+    /// several into the one needing the most work.
+    pub fn poll(&self) -> Option<Change> {
+        self.changes.try_iter().max()
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+#[derive(PartialEq, Eq)]
+struct Snapshot {
+    len: u64,
+    modified: Option<SystemTime>,
+    /// Identifies the file itself, so a rotated file is noticed even if the
+    /// new one is larger.
+    inode: u64,
+}
+
+impl Snapshot {
+    fn take(path: &Path) -> Option<Snapshot> {
+        let metadata = fs::metadata(path).ok()?;
+        Some(Snapshot {
+            len: metadata.len(),
+            modified: metadata.modified().ok(),
+            inode: inode(&metadata),
+        })
+    }
+
+    fn classify(before: Option<&Snapshot>, after: Option<&Snapshot>) -> Change {
+        match (before, after) {
+            (Some(before), Some(after))
+                if after.inode == before.inode && after.len > before.len =>
+            {
+                Change::Appended
+            }
+            _ => Change::Rewritten,
+        }
+    }
+}
+
+/// Inode number of the file, telling a file rewritten in place from one
+/// replaced by another, or 0 where there are none.
+#[cfg(unix)]
+pub fn inode(metadata: &fs::Metadata) -> u64 {
+    std::os::unix::fs::MetadataExt::ino(metadata)
+}
+
+#[cfg(not(unix))]
+pub fn inode(_metadata: &fs::Metadata) -> u64 {
+    0
+}